@@ -0,0 +1,54 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+
+use ggez_test2::broadphase::*;
+use ggez_test2::collision::Point;
+use ggez_test2::components::CollisionFilter;
+use ggez_test2::systems::CONTACT_GRID_CELL_SIZE;
+use ggez_test2::world::Entity;
+
+/// Particle sized boxes scattered over the default screen, the same as `generate_entities`
+fn particles(count: usize) -> Vec<Proxy> {
+    let mut rng = Pcg32::seed_from_u64(count as u64);
+    (0..count)
+        .map(|index| {
+            let center = Point::new(rng.gen::<f32>() * 1280.0, rng.gen::<f32>() * 1024.0);
+            Proxy {
+                entity: Entity { index, generation: 0 },
+                min: Point::new(center.x - 4.0, center.y - 4.0),
+                max: Point::new(center.x + 4.0, center.y + 4.0),
+                filter: CollisionFilter::default(),
+            }
+        })
+        .collect()
+}
+
+fn broadphase(c: &mut Criterion) {
+    let mut group = c.benchmark_group("broadphase");
+    group.sample_size(10);
+
+    for count in [1_000, 10_000, 50_000].iter() {
+        let proxies = particles(*count);
+
+        group.bench_with_input(BenchmarkId::new("uniform_grid", count), &proxies, |b, proxies| {
+            let mut grid = UniformGrid::new(CONTACT_GRID_CELL_SIZE);
+            b.iter(|| grid.pairs(proxies))
+        });
+        group.bench_with_input(BenchmarkId::new("sweep_and_prune", count), &proxies, |b, proxies| {
+            let mut sap = SweepAndPrune::new();
+            b.iter(|| sap.pairs(proxies))
+        });
+        // quadratic, only worth running at the smallest size
+        if *count <= 1_000 {
+            group.bench_with_input(BenchmarkId::new("brute_force", count), &proxies, |b, proxies| {
+                b.iter(|| BruteForce.pairs(proxies))
+            });
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, broadphase);
+criterion_main!(benches);
@@ -0,0 +1,140 @@
+//! Runs the ggez-test2 simulation without opening a window, e.g. on CI.
+//!
+//! usage: headless [--seed S] [--ticks N] [--width W] [--height H] [--spawn-every N] [--dump]
+//!                 [--record FILE] [--replay FILE]
+//!
+//! `--replay` plays a recording made here or in the windowed game and exits with an error if
+//! the world diverges from the recorded checksums.
+
+use std::env;
+use std::path::PathBuf;
+use std::process;
+
+use ggez::event::MouseButton;
+use ggez::graphics::Rect;
+
+use ggez_test2::components::*;
+use ggez_test2::replay::*;
+use ggez_test2::simulation::*;
+
+const USAGE: &str = "usage: headless [--seed S] [--ticks N] [--width W] [--height H] [--spawn-every N] [--dump] [--record FILE] [--replay FILE]";
+
+struct Options {
+    seed: u64,
+    /// defaults to the length of the replay when replaying
+    ticks: Option<u64>,
+    width: f32,
+    height: f32,
+    spawn_every: u64,
+    dump: bool,
+    record: Option<PathBuf>,
+    replay: Option<PathBuf>,
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{} needs a value", flag))?;
+    value.parse().map_err(|_| format!("invalid value for {}: {}", flag, value))
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options {
+        seed: rand::random(),
+        ticks: None,
+        width: 1280.0,
+        height: 1024.0,
+        spawn_every: 0,
+        dump: false,
+        record: None,
+        replay: None,
+    };
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--seed" => options.seed = parse_value(&arg, args.next())?,
+            "--ticks" => options.ticks = Some(parse_value(&arg, args.next())?),
+            "--width" => options.width = parse_value(&arg, args.next())?,
+            "--height" => options.height = parse_value(&arg, args.next())?,
+            "--spawn-every" => options.spawn_every = parse_value(&arg, args.next())?,
+            "--dump" => options.dump = true,
+            "--record" => options.record = Some(parse_value(&arg, args.next())?),
+            "--replay" => options.replay = Some(parse_value(&arg, args.next())?),
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+
+    Ok(options)
+}
+
+fn main() {
+    let options = match parse_args() {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("ERROR: {}", e);
+            eprintln!("{}", USAGE);
+            process::exit(1);
+        },
+    };
+
+    let mut sim = match &options.replay {
+        Some(path) => match Recording::load(path) {
+            Ok(recording) => Simulation::from_recording(recording),
+            Err(e) => {
+                eprintln!("ERROR: could not load {}: {}", path.display(), e);
+                process::exit(1);
+            },
+        },
+        None => {
+            let mut sim = Simulation::new(Rect::new(0.0, 0.0, options.width, options.height), options.seed);
+            if options.record.is_some() {
+                sim.start_recording();
+            }
+            sim.populate();
+            sim
+        },
+    };
+
+    let ticks = match (&options.ticks, &sim.replay) {
+        (Some(ticks), _) => *ticks,
+        (None, Some(replay)) => replay.recording.checksums.len() as u64,
+        (None, None) => 600,
+    };
+    for tick in 1..=ticks {
+        // spawning goes through the input queue, the same way a mouse click does
+        if options.spawn_every > 0 && tick % options.spawn_every == 0 {
+            sim.queue_input(InputEvent::MouseDown { button: MouseButton::Left, x: 0.0, y: 0.0 });
+        }
+        sim.step();
+    }
+
+    if let (Some(path), Some(recording)) = (&options.record, &sim.recording) {
+        if let Err(e) = recording.save(path) {
+            eprintln!("ERROR: could not save recording to {}: {}", path.display(), e);
+            process::exit(1);
+        }
+    }
+
+    let world = &sim.world;
+    println!("seed {}, tick {}: {} live entities", world.rng.seed(), world.time.tick, world.entity_allocator.live_entity_count());
+
+    if options.dump {
+        for e in world.live_entities() {
+            let mut line = format!("{:?}", world.debug_entity(e));
+            if let Some(p) = world.get::<Position>(e) {
+                line += &format!(" pos=({:.3}, {:.3})", p.x, p.y);
+            }
+            if let Some(v) = world.get::<Velocity>(e) {
+                line += &format!(" vel=({:.3}, {:.3})", v.xv, v.yv);
+            }
+            println!("{}", line);
+        }
+    }
+
+    if let Some(desync) = sim.desync {
+        eprintln!(
+            "DESYNC at tick {}: expected checksum {:016x}, found {:016x}, differing components: {}",
+            desync.tick, desync.expected, desync.found, desync.components.join(", ")
+        );
+        process::exit(2);
+    }
+}
@@ -0,0 +1,312 @@
+use crate::collision::Point;
+use crate::components::CollisionFilter;
+use crate::world::Entity;
+
+/// Bounding box of one shape, the input to a broadphase
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Proxy {
+    pub entity: Entity,
+    pub min: Point,
+    pub max: Point,
+    pub filter: CollisionFilter,
+}
+
+impl Proxy {
+    /// Whether a broadphase pairs the two proxies up: their boxes overlap and their filters let
+    /// them interact
+    pub fn pairs_with(&self, other: &Proxy) -> bool {
+        self.overlaps(other) && self.filter.interacts(&other.filter)
+    }
+
+    /// Boxes that only touch don't overlap, the same as in the narrowphase
+    pub fn overlaps(&self, other: &Proxy) -> bool {
+        self.overlaps_box(other.min, other.max)
+    }
+
+    pub fn overlaps_box(&self, min: Point, max: Point) -> bool {
+        self.min.x < max.x && min.x < self.max.x && self.min.y < max.y && min.y < self.max.y
+    }
+}
+
+/// Finds the pairs of proxies whose boxes overlap and whose filters let them interact, so the
+/// narrowphase only has to look at those.
+/// Pairs are indices into `proxies`, `(i, j)` with `i < j`, sorted so the result doesn't depend
+/// on the implementation and the simulation stays deterministic.
+pub trait Broadphase {
+    fn pairs(&mut self, proxies: &[Proxy]) -> Vec<(usize, usize)>;
+}
+
+/// Tests every pair. Only useful as a reference for the others.
+pub struct BruteForce;
+
+impl Broadphase for BruteForce {
+    fn pairs(&mut self, proxies: &[Proxy]) -> Vec<(usize, usize)> {
+        let mut pairs = Vec::new();
+        for (i, a) in proxies.iter().enumerate() {
+            for (j, b) in proxies.iter().enumerate().skip(i + 1) {
+                if a.pairs_with(b) {
+                    pairs.push((i, j));
+                }
+            }
+        }
+        pairs
+    }
+}
+
+/// Buckets proxies into square cells and only tests proxies sharing a cell. Works best when the
+/// cell size is around the size of the typical shape.
+pub struct UniformGrid {
+    cell_size: f32,
+    /// (cell, proxy) for every cell a proxy covers, reused between calls
+    entries: Vec<(u64, usize)>,
+}
+
+impl UniformGrid {
+    pub fn new(cell_size: f32) -> Self {
+        UniformGrid {
+            cell_size,
+            entries: Vec::new(),
+        }
+    }
+}
+
+/// Calls `f` with the key of every cell of size `cell_size` the box from `min` to `max` covers
+fn for_each_cell(cell_size: f32, min: Point, max: Point, mut f: impl FnMut(u64)) {
+    let cell = |v: f32| (v / cell_size).floor() as i32;
+    for y in cell(min.y)..=cell(max.y) {
+        for x in cell(min.x)..=cell(max.x) {
+            f(((y as u32 as u64) << 32) | x as u32 as u64);
+        }
+    }
+}
+
+impl Broadphase for UniformGrid {
+    fn pairs(&mut self, proxies: &[Proxy]) -> Vec<(usize, usize)> {
+        self.entries.clear();
+        for (i, proxy) in proxies.iter().enumerate() {
+            let entries = &mut self.entries;
+            for_each_cell(self.cell_size, proxy.min, proxy.max, |key| entries.push((key, i)));
+        }
+        // sorting instead of hashing keeps cells in a fixed order without allocating per cell
+        self.entries.sort_unstable();
+
+        let mut pairs = Vec::new();
+        let mut start = 0;
+        while start < self.entries.len() {
+            let key = self.entries[start].0;
+            let end = start + self.entries[start..].iter().take_while(|(k, _)| *k == key).count();
+            let cell = &self.entries[start..end];
+            for (n, (_, i)) in cell.iter().enumerate() {
+                for (_, j) in &cell[n + 1..] {
+                    if proxies[*i].pairs_with(&proxies[*j]) {
+                        pairs.push((*i, *j));
+                    }
+                }
+            }
+            start = end;
+        }
+
+        // shapes covering several cells are found once per shared cell
+        pairs.sort_unstable();
+        pairs.dedup();
+        pairs
+    }
+}
+
+/// A uniform grid that is built once and then queried many times, for shapes that rarely move
+/// such as sleeping bodies
+pub struct GridIndex {
+    cell_size: f32,
+    proxies: Vec<Proxy>,
+    /// (cell, proxy) for every cell a proxy covers, sorted by cell
+    entries: Vec<(u64, usize)>,
+}
+
+impl GridIndex {
+    pub fn new(cell_size: f32) -> Self {
+        GridIndex {
+            cell_size,
+            proxies: Vec::new(),
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn proxies(&self) -> &[Proxy] {
+        &self.proxies
+    }
+
+    /// Replaces everything in the index with `proxies`
+    pub fn rebuild(&mut self, proxies: Vec<Proxy>) {
+        self.entries.clear();
+        for (i, proxy) in proxies.iter().enumerate() {
+            let entries = &mut self.entries;
+            for_each_cell(self.cell_size, proxy.min, proxy.max, |key| entries.push((key, i)));
+        }
+        self.entries.sort_unstable();
+        self.proxies = proxies;
+    }
+
+    /// Indices into `proxies` of every proxy overlapping the box from `min` to `max`, in
+    /// ascending order. `found` is cleared first so it can be reused between queries.
+    pub fn query(&self, min: Point, max: Point, found: &mut Vec<usize>) {
+        found.clear();
+        for_each_cell(self.cell_size, min, max, |key| {
+            let start = self.entries.partition_point(|(k, _)| *k < key);
+            for (k, i) in &self.entries[start..] {
+                if *k != key {
+                    break;
+                }
+                if self.proxies[*i].overlaps_box(min, max) {
+                    found.push(*i);
+                }
+            }
+        });
+        found.sort_unstable();
+        found.dedup();
+    }
+}
+
+/// Sorts proxies along the x axis and sweeps across them, only testing proxies whose x ranges
+/// overlap. Needs no tuning, but degrades when many shapes line up vertically.
+#[derive(Default)]
+pub struct SweepAndPrune {
+    /// proxy indices sorted by left edge, reused between calls
+    order: Vec<usize>,
+}
+
+impl SweepAndPrune {
+    pub fn new() -> Self {
+        SweepAndPrune { order: Vec::new() }
+    }
+}
+
+impl Broadphase for SweepAndPrune {
+    fn pairs(&mut self, proxies: &[Proxy]) -> Vec<(usize, usize)> {
+        self.order.clear();
+        self.order.extend(0..proxies.len());
+        // a NaN position must not bring the sweep down, it just never overlaps anything
+        self.order.sort_by(|a, b| proxies[*a].min.x.total_cmp(&proxies[*b].min.x));
+
+        let mut pairs = Vec::new();
+        for (n, i) in self.order.iter().enumerate() {
+            let a = &proxies[*i];
+            for j in &self.order[n + 1..] {
+                let b = &proxies[*j];
+                if b.min.x >= a.max.x {
+                    break;
+                }
+                if a.pairs_with(b) {
+                    pairs.push((*i.min(j), *i.max(j)));
+                }
+            }
+        }
+
+        pairs.sort_unstable();
+        pairs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand_pcg::Pcg32;
+
+    fn proxies(count: usize, seed: u64) -> Vec<Proxy> {
+        let mut rng = Pcg32::seed_from_u64(seed);
+        (0..count)
+            .map(|index| {
+                // mostly particle sized boxes, some large ones spanning many cells, and a pile of
+                // boxes sharing the exact same spot
+                let size = match index % 10 {
+                    0 => rng.gen_range(20.0, 120.0),
+                    _ => rng.gen_range(2.0, 10.0),
+                };
+                let min = if index % 7 == 0 {
+                    Point::new(300.0, 300.0)
+                } else {
+                    Point::new(rng.gen_range(-100.0, 1280.0), rng.gen_range(-100.0, 1024.0))
+                };
+                // a few layers that don't all see each other, so filtering gets checked too
+                let layer = 1 << (index % 3);
+                Proxy {
+                    entity: Entity { index, generation: 0 },
+                    min,
+                    max: Point::new(min.x + size, min.y + size),
+                    filter: CollisionFilter::new(layer, CollisionFilter::ALL_LAYERS & !(layer << 1)),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        for seed in 0..5 {
+            let proxies = proxies(2000, seed);
+            let expected = BruteForce.pairs(&proxies);
+
+            assert!(!expected.is_empty());
+            assert_eq!(UniformGrid::new(16.0).pairs(&proxies), expected);
+            assert_eq!(UniformGrid::new(100.0).pairs(&proxies), expected);
+            assert_eq!(SweepAndPrune::new().pairs(&proxies), expected);
+        }
+    }
+
+    #[test]
+    fn grid_index_matches_brute_force() {
+        let proxies = proxies(2000, 9);
+        let mut index = GridIndex::new(16.0);
+        index.rebuild(proxies.clone());
+
+        let mut found = Vec::new();
+        for query in proxies.iter().step_by(37) {
+            index.query(query.min, query.max, &mut found);
+            let expected: Vec<usize> = (0..proxies.len()).filter(|i| proxies[*i].overlaps(query)).collect();
+            assert_eq!(found, expected);
+        }
+    }
+
+    fn proxy(index: usize, min: Point, max: Point, filter: CollisionFilter) -> Proxy {
+        Proxy { entity: Entity { index, generation: 0 }, min, max, filter }
+    }
+
+    #[test]
+    fn touching_boxes_are_not_paired() {
+        let proxies = vec![
+            proxy(0, Point::new(0.0, 0.0), Point::new(16.0, 16.0), CollisionFilter::default()),
+            proxy(1, Point::new(16.0, 0.0), Point::new(32.0, 16.0), CollisionFilter::default()),
+        ];
+
+        assert!(BruteForce.pairs(&proxies).is_empty());
+        assert!(UniformGrid::new(16.0).pairs(&proxies).is_empty());
+        assert!(SweepAndPrune::new().pairs(&proxies).is_empty());
+    }
+
+    #[test]
+    fn filtered_proxies_are_not_paired() {
+        let (min, max) = (Point::new(0.0, 0.0), Point::new(16.0, 16.0));
+        let proxies = vec![
+            proxy(0, min, max, CollisionFilter::new(0b01, 0b10)),
+            proxy(1, min, max, CollisionFilter::new(0b01, 0b10)),
+            proxy(2, min, max, CollisionFilter::new(0b10, 0b11)),
+            // sees layer 1, but layer 1 doesn't see it back
+            proxy(3, min, max, CollisionFilter::new(0b100, 0b01)),
+        ];
+        let expected = vec![(0, 2), (1, 2)];
+
+        assert_eq!(BruteForce.pairs(&proxies), expected);
+        assert_eq!(UniformGrid::new(16.0).pairs(&proxies), expected);
+        assert_eq!(SweepAndPrune::new().pairs(&proxies), expected);
+    }
+
+    #[test]
+    fn nan_proxies_are_ignored() {
+        let mut proxies = proxies(100, 0);
+        proxies[3].min = Point::new(f32::NAN, f32::NAN);
+        proxies[3].max = Point::new(f32::NAN, f32::NAN);
+        let expected = BruteForce.pairs(&proxies);
+
+        assert!(expected.iter().all(|(i, j)| *i != 3 && *j != 3));
+        assert_eq!(SweepAndPrune::new().pairs(&proxies), expected);
+    }
+}
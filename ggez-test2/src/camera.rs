@@ -0,0 +1,204 @@
+use ggez::graphics::Rect;
+
+use crate::collision::*;
+use crate::render::View;
+use crate::world::*;
+
+/// What part of the world is drawn, stored on the `World`. The world point shown in the middle
+/// of the viewport is `position` plus the middle of the viewport, so the default camera draws
+/// world coordinates as screen pixels. Zooming and turning happen around the middle of the
+/// viewport.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Camera2D {
+    pub position: Vector,
+    pub zoom: f32,
+    /// radians, turning the camera clockwise turns the world the other way on screen
+    pub rotation: f32,
+    /// entity kept in the middle of the viewport by `CameraSystem`
+    pub target: Option<Entity>,
+    /// how quickly the camera catches up with its target, in 1/seconds. Infinity snaps to it.
+    pub follow_speed: f32,
+    /// how hard the view shakes, 0..1, see `shake`
+    pub trauma: f32,
+    /// trauma lost per second
+    pub shake_decay: f32,
+    /// how far the view moves, in pixels, and turns, in radians, at full trauma
+    pub max_shake_offset: f32,
+    pub max_shake_angle: f32,
+    /// seconds spent shaking, picks the shake offsets
+    shake_time: f32,
+    /// position at the end of the previous step, for interpolated drawing
+    previous_position: Vector,
+}
+
+impl Default for Camera2D {
+    fn default() -> Self {
+        Camera2D {
+            position: Vector::zeros(),
+            zoom: 1.0,
+            rotation: 0.0,
+            target: None,
+            follow_speed: 5.0,
+            trauma: 0.0,
+            shake_decay: 1.5,
+            max_shake_offset: 12.0,
+            max_shake_angle: 0.05,
+            shake_time: 0.0,
+            previous_position: Vector::zeros(),
+        }
+    }
+}
+
+impl Camera2D {
+    pub fn follow(&mut self, target: Entity) {
+        self.target = Some(target);
+    }
+
+    /// Adds `amount` of trauma. The view shakes with the square of it, so small knocks barely
+    /// show and big ones stand out.
+    pub fn shake(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).clamp(0.0, 1.0);
+    }
+
+    /// Moves towards `target`, if there is one, and runs down the trauma over a step of `dt`
+    pub fn update(&mut self, target: Option<Point>, viewport: Rect, dt: f32) {
+        self.previous_position = self.position;
+        if let Some(target) = target {
+            let wanted = target - center(viewport);
+            self.position += (wanted - self.position) * (1.0 - (-self.follow_speed * dt).exp());
+        }
+        if self.trauma > 0.0 {
+            self.shake_time += dt;
+            self.trauma = (self.trauma - self.shake_decay * dt).max(0.0);
+        }
+    }
+
+    /// Moves the camera without a trail, so the next frame isn't drawn between the old and the
+    /// new position
+    pub fn teleport(&mut self, position: Vector) {
+        self.position = position;
+        self.previous_position = position;
+    }
+
+    /// How far the view is moved and turned by the shake right now
+    pub fn shake_offset(&self) -> (Vector, f32) {
+        // a few out of step waves, smooth but without an obvious pattern
+        let wave = |seed: f32| (self.shake_time * (23.0 + seed * 7.0)).sin() * (self.shake_time * (11.0 + seed * 3.0)).cos();
+        let strength = self.trauma * self.trauma;
+        let offset = Vector::new(wave(1.0), wave(2.0)) * self.max_shake_offset * strength;
+        (offset, wave(3.0) * self.max_shake_angle * strength)
+    }
+
+    /// The view drawn into `viewport`, `alpha` of the way from the previous step to this one
+    pub fn view(&self, viewport: Rect, alpha: f32) -> View {
+        let (shake, shake_angle) = self.shake_offset();
+        let position = self.previous_position + (self.position - self.previous_position) * alpha + shake;
+        let rotation = shake_angle - self.rotation;
+        let middle = center(viewport);
+        let (sin, cos) = rotation.sin_cos();
+        let look_at = (position + middle.coords) * self.zoom;
+        let turned = Vector::new(look_at.x * cos - look_at.y * sin, look_at.x * sin + look_at.y * cos);
+        View { translation: middle.coords - turned, rotation, zoom: self.zoom }
+    }
+}
+
+fn center(viewport: Rect) -> Point {
+    Point::new(viewport.x + viewport.w / 2.0, viewport.y + viewport.h / 2.0)
+}
+
+/// Conversions through `camera` into `screen`, as drawn on the current frame
+impl World {
+    pub fn world_to_screen(&self, point: Point) -> Point {
+        self.camera.view(self.screen, self.time.alpha).to_screen(point)
+    }
+
+    /// Where a point on the screen, like the mouse, is in the world
+    pub fn screen_to_world(&self, point: Point) -> Point {
+        self.camera.view(self.screen, self.time.alpha).to_world(point)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn viewport() -> Rect {
+        Rect::new(0.0, 0.0, 400.0, 300.0)
+    }
+
+    fn assert_close(a: Point, b: Point) {
+        assert!((a - b).norm() < 1e-3, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn the_default_camera_draws_world_coordinates_as_pixels() {
+        let camera = Camera2D::default();
+        for viewport in &[viewport(), Rect::new(50.0, -20.0, 10.0, 10.0)] {
+            let view = camera.view(*viewport, 1.0);
+            assert_close(view.to_screen(Point::new(12.0, 34.0)), Point::new(12.0, 34.0));
+        }
+    }
+
+    #[test]
+    fn zoom_and_rotation_turn_around_the_middle() {
+        let mut camera = Camera2D { zoom: 2.0, ..Camera2D::default() };
+        let view = camera.view(viewport(), 1.0);
+        assert_close(view.to_screen(Point::new(200.0, 150.0)), Point::new(200.0, 150.0));
+        assert_close(view.to_screen(Point::new(210.0, 150.0)), Point::new(220.0, 150.0));
+
+        // turning the camera clockwise turns the world anticlockwise
+        camera.rotation = std::f32::consts::FRAC_PI_2;
+        camera.position = Vector::new(100.0, 0.0);
+        let view = camera.view(viewport(), 1.0);
+        assert_close(view.to_screen(Point::new(300.0, 150.0)), Point::new(200.0, 150.0));
+        assert_close(view.to_screen(Point::new(310.0, 150.0)), Point::new(200.0, 130.0));
+        let p = Point::new(-5.0, 77.0);
+        assert_close(view.to_world(view.to_screen(p)), p);
+    }
+
+    #[test]
+    fn following_a_target() {
+        let mut camera = Camera2D::default();
+        let target = Point::new(500.0, 150.0);
+        camera.update(Some(target), viewport(), 1.0 / 64.0);
+        let first = camera.position.x;
+        assert!(first > 0.0 && first < 300.0);
+        // drawing between the steps starts where the camera was
+        assert_close(camera.view(viewport(), 0.0).to_screen(target), Point::new(500.0, 150.0));
+
+        for _ in 0..256 {
+            camera.update(Some(target), viewport(), 1.0 / 64.0);
+        }
+        assert_close(camera.view(viewport(), 1.0).to_screen(target), Point::new(200.0, 150.0));
+
+        let mut snapping = Camera2D { follow_speed: f32::INFINITY, ..Camera2D::default() };
+        snapping.update(Some(target), viewport(), 1.0 / 64.0);
+        assert_eq!(snapping.position, Vector::new(300.0, 0.0));
+        snapping.teleport(Vector::zeros());
+        assert_eq!(snapping.view(viewport(), 0.0), Camera2D::default().view(viewport(), 0.0));
+    }
+
+    #[test]
+    fn shaking_wears_off() {
+        let mut camera = Camera2D::default();
+        assert_eq!(camera.shake_offset(), (Vector::zeros(), 0.0));
+        camera.shake(0.5);
+        camera.shake(0.7);
+        assert_eq!(camera.trauma, 1.0);
+
+        let mut moved = false;
+        for _ in 0..32 {
+            camera.update(None, viewport(), 1.0 / 64.0);
+            let (offset, angle) = camera.shake_offset();
+            assert!(offset.x.abs() <= 12.0 && offset.y.abs() <= 12.0 && angle.abs() <= 0.05);
+            moved |= offset.norm() > 1.0;
+        }
+        assert!(moved);
+        for _ in 0..64 {
+            camera.update(None, viewport(), 1.0 / 64.0);
+        }
+        assert_eq!(camera.trauma, 0.0);
+        assert_eq!(camera.shake_offset(), (Vector::zeros(), 0.0));
+        assert_eq!(camera.position, Vector::zeros());
+    }
+}
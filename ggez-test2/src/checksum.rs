@@ -0,0 +1,280 @@
+use crate::reflect::Reflect;
+use crate::world::*;
+
+/// 64 bit FNV-1a. Unlike `DefaultHasher` its output is fixed, so checksums can be
+/// stored in files and compared between builds and machines.
+#[derive(Debug, Copy, Clone)]
+pub struct StableHasher(u64);
+
+impl StableHasher {
+    pub fn new() -> Self {
+        StableHasher(0xcbf2_9ce4_8422_2325)
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    /// Hashes the exact bit pattern, so `0.0` and `-0.0` differ
+    pub fn write_f32(&mut self, value: f32) {
+        self.write_bytes(&value.to_bits().to_le_bytes());
+    }
+
+    pub fn write_str(&mut self, value: &str) {
+        self.write_u64(value.len() as u64);
+        self.write_bytes(value.as_bytes());
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        StableHasher::new()
+    }
+}
+
+/// Hashes any reflected value by walking its fields, so every component is covered without
+/// writing a hash function for each one
+pub fn hash_reflect(value: &dyn Reflect, hasher: &mut StableHasher) {
+    if let Some(variant) = value.variant() {
+        hasher.write_str(variant);
+    }
+
+    let mut has_fields = false;
+    value.visit_fields(&mut |_, field| {
+        has_fields = true;
+        hash_reflect(field, hasher);
+    });
+    if !has_fields {
+        hash_leaf(value, hasher);
+    }
+}
+
+fn hash_leaf(value: &dyn Reflect, hasher: &mut StableHasher) {
+    let any = value.as_any();
+    if let Some(v) = any.downcast_ref::<f32>() {
+        hasher.write_f32(*v);
+    } else if let Some(v) = any.downcast_ref::<f64>() {
+        hasher.write_u64(v.to_bits());
+    } else if let Some(v) = any.downcast_ref::<String>() {
+        hasher.write_str(v);
+    } else if let Some(v) = any.downcast_ref::<bool>() {
+        hasher.write_u64(*v as u64);
+    } else if let Some(v) = any.downcast_ref::<u8>() {
+        hasher.write_u64(*v as u64);
+    } else if let Some(v) = any.downcast_ref::<u16>() {
+        hasher.write_u64(*v as u64);
+    } else if let Some(v) = any.downcast_ref::<u32>() {
+        hasher.write_u64(*v as u64);
+    } else if let Some(v) = any.downcast_ref::<u64>() {
+        hasher.write_u64(*v);
+    } else if let Some(v) = any.downcast_ref::<usize>() {
+        hasher.write_u64(*v as u64);
+    } else if let Some(v) = any.downcast_ref::<i32>() {
+        hasher.write_u64(*v as u64);
+    } else if let Some(v) = any.downcast_ref::<i64>() {
+        hasher.write_u64(*v as u64);
+    } else {
+        // no fields and not a plain value, e.g. a tag component. Its presence is what counts.
+        hasher.write_str(value.type_name());
+    }
+}
+
+/// World checksum with a separate hash for each component type, so a mismatch can be
+/// narrowed down to the components that differ
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WorldChecksum {
+    pub total: u64,
+    pub components: Vec<(String, u64)>,
+}
+
+impl WorldChecksum {
+    /// Names of the component types whose hashes differ between the two checksums
+    pub fn differing_components(&self, other: &WorldChecksum) -> Vec<String> {
+        let mut names: Vec<String> = self.components.iter()
+            .filter(|(name, hash)| other.components.iter().find(|(n, _)| n == name).map(|(_, h)| h) != Some(hash))
+            .map(|(name, _)| name.clone())
+            .collect();
+        for (name, _) in &other.components {
+            if !self.components.iter().any(|(n, _)| n == name) {
+                names.push(name.clone());
+            }
+        }
+        names
+    }
+}
+
+/// Per entity, per component hashes of a world, for finding exactly where two runs diverged
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChecksumSnapshot {
+    pub entities: Vec<(Entity, Vec<(&'static str, u64)>)>,
+}
+
+/// The first place two snapshots disagree
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Difference {
+    pub entity: Entity,
+    /// `None` if the entity is only alive in one of the worlds
+    pub component: Option<&'static str>,
+}
+
+impl ChecksumSnapshot {
+    /// Walks both snapshots in entity order and reports the first entity and component that
+    /// differ, either in value or in whether they exist at all
+    pub fn first_difference(&self, other: &ChecksumSnapshot) -> Option<Difference> {
+        let mut a = self.entities.iter().peekable();
+        let mut b = other.entities.iter().peekable();
+        loop {
+            match (a.peek(), b.peek()) {
+                (None, None) => return None,
+                (Some((e, _)), None) | (None, Some((e, _))) => return Some(Difference { entity: *e, component: None }),
+                (Some((ea, ca)), Some((eb, cb))) => {
+                    if ea != eb {
+                        let entity = if (ea.index, ea.generation) < (eb.index, eb.generation) { *ea } else { *eb };
+                        return Some(Difference { entity, component: None });
+                    }
+                    for name in World::component_types() {
+                        let ha = ca.iter().find(|(n, _)| n == name);
+                        let hb = cb.iter().find(|(n, _)| n == name);
+                        if ha != hb {
+                            return Some(Difference { entity: *ea, component: Some(name) });
+                        }
+                    }
+                    a.next();
+                    b.next();
+                },
+            }
+        }
+    }
+}
+
+impl World {
+    /// Index in `component_types`, name and hash of every component attached to `e`
+    fn component_hashes(&self, e: Entity) -> impl Iterator<Item = (usize, &'static str, u64)> + '_ {
+        World::component_types().iter()
+            .zip(self.reflect_components(e))
+            .enumerate()
+            .filter_map(|(i, (name, value))| {
+                value.map(|value| {
+                    let mut hasher = StableHasher::new();
+                    hash_reflect(value, &mut hasher);
+                    (i, *name, hasher.finish())
+                })
+            })
+    }
+
+    /// Stable hash of all live entities and all their component values. Floats are hashed
+    /// by their bits, so any difference at all changes the checksum.
+    pub fn checksum(&self) -> u64 {
+        self.checksum_breakdown().total
+    }
+
+    /// The checksum plus one hash for each registered component type
+    pub fn checksum_breakdown(&self) -> WorldChecksum {
+        let mut total = StableHasher::new();
+        let mut per_component: Vec<StableHasher> = World::component_types().iter().map(|_| StableHasher::new()).collect();
+
+        for e in self.live_entities() {
+            total.write_u64(e.index as u64);
+            total.write_u64(e.generation);
+            for (i, name, hash) in self.component_hashes(e) {
+                total.write_str(name);
+                total.write_u64(hash);
+
+                let hasher = &mut per_component[i];
+                hasher.write_u64(e.index as u64);
+                hasher.write_u64(e.generation);
+                hasher.write_u64(hash);
+            }
+        }
+
+        WorldChecksum {
+            total: total.finish(),
+            components: World::component_types().iter()
+                .zip(per_component)
+                .map(|(name, hasher)| (name.to_string(), hasher.finish()))
+                .collect(),
+        }
+    }
+
+    pub fn checksum_snapshot(&self) -> ChecksumSnapshot {
+        let mut entities: Vec<(Entity, Vec<(&'static str, u64)>)> = self.live_entities()
+            .map(|e| {
+                (e, self.component_hashes(e).map(|(_, name, hash)| (name, hash)).collect())
+            })
+            .collect();
+        entities.sort_by_key(|(e, _)| (e.index, e.generation));
+        ChecksumSnapshot { entities }
+    }
+
+    /// The first entity and component that differ between two worlds, `None` if they match
+    pub fn first_difference(&self, other: &World) -> Option<Difference> {
+        self.checksum_snapshot().first_difference(&other.checksum_snapshot())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::*;
+
+    fn world() -> (World, Entity, Entity) {
+        let mut world = World::new();
+        let a = world.spawn();
+        world.set(a, Position { x: 1.0, y: 2.0 });
+        world.set(a, Velocity { xv: 0.5, yv: 0.0 });
+        let b = world.spawn();
+        world.set(b, Position { x: 3.0, y: 4.0 });
+        world.set(b, Shape { shape_type: ShapeType::Circle(4.0), colour: ggez::graphics::WHITE });
+        world.set(b, Particle);
+        (world, a, b)
+    }
+
+    #[test]
+    fn equal_worlds_have_equal_checksums() {
+        let (w1, _, _) = world();
+        let (w2, _, _) = world();
+
+        assert_eq!(w1.checksum(), w2.checksum());
+        assert_eq!(w1.checksum_breakdown(), w2.checksum_breakdown());
+        assert_eq!(w1.first_difference(&w2), None);
+    }
+
+    #[test]
+    fn checksum_is_bit_exact() {
+        let (w1, a, _) = world();
+        let (mut w2, _, _) = world();
+        w2.get_mut::<Velocity>(a).unwrap().yv = -0.0;
+
+        assert_ne!(w1.checksum(), w2.checksum());
+        assert_eq!(w1.checksum_breakdown().differing_components(&w2.checksum_breakdown()), vec!["Velocity"]);
+        assert_eq!(w1.first_difference(&w2), Some(Difference { entity: a, component: Some("Velocity") }));
+    }
+
+    #[test]
+    fn finds_first_differing_entity_and_component() {
+        let (w1, _, b) = world();
+        let (mut w2, _, _) = world();
+        w2.set(b, Shape { shape_type: ShapeType::Rectangle(4.0, 4.0), colour: ggez::graphics::WHITE });
+        assert_eq!(w1.first_difference(&w2), Some(Difference { entity: b, component: Some("Shape") }));
+
+        let (mut w3, _, _) = world();
+        w3.remove::<Particle>(b);
+        assert_eq!(w1.first_difference(&w3), Some(Difference { entity: b, component: Some("Particle") }));
+        assert_eq!(w1.checksum_breakdown().differing_components(&w3.checksum_breakdown()), vec!["Particle"]);
+
+        let (mut w4, _, _) = world();
+        let c = w4.spawn();
+        assert_eq!(w1.first_difference(&w4), Some(Difference { entity: c, component: None }));
+    }
+}
@@ -0,0 +1,896 @@
+use ggez::nalgebra as na;
+
+use crate::components::*;
+use crate::world::*;
+
+pub type Point = na::Point2<f32>;
+pub type Vector = na::Vector2<f32>;
+
+/// A shape placed in the world, ready for overlap tests
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Collider {
+    Circle { center: Point, radius: f32 },
+    Aabb { min: Point, max: Point },
+    /// corners in order around a convex outline, only the first `count` are used
+    Polygon { vertices: [Point; MAX_POLYGON_VERTICES], count: usize },
+    /// everything within `radius` of the line from `a` to `b`, a segment if `radius` is 0
+    Capsule { a: Point, b: Point, radius: f32 },
+}
+
+impl Collider {
+    /// The shape placed at `position` by its own origin and not turned, see `Shape::collider`
+    pub fn new(position: &Position, shape: &Shape) -> Self {
+        shape.collider(position, 0.0, Anchor::Origin)
+    }
+
+    /// `corners` must go around a convex outline, and there can't be more than
+    /// `MAX_POLYGON_VERTICES` of them
+    pub fn polygon(corners: &[Point]) -> Self {
+        let mut vertices = [Point::origin(); MAX_POLYGON_VERTICES];
+        vertices[..corners.len()].copy_from_slice(corners);
+        Collider::Polygon { vertices, count: corners.len() }
+    }
+
+    /// Smallest axis aligned box containing the collider, as (min, max)
+    pub fn bounds(&self) -> (Point, Point) {
+        match *self {
+            Collider::Circle { center, radius } => {
+                (center - Vector::new(radius, radius), center + Vector::new(radius, radius))
+            },
+            Collider::Aabb { min, max } => (min, max),
+            Collider::Polygon { .. } | Collider::Capsule { .. } => {
+                let hull = self.hull();
+                let mut min = hull.points()[0];
+                let mut max = min;
+                for p in &hull.points()[1..] {
+                    min = Point::new(min.x.min(p.x), min.y.min(p.y));
+                    max = Point::new(max.x.max(p.x), max.y.max(p.y));
+                }
+                let radius = Vector::new(hull.radius, hull.radius);
+                (min - radius, max + radius)
+            },
+        }
+    }
+
+    /// Points on the outline don't count, the same as touching shapes don't collide
+    pub fn contains(&self, point: Point) -> bool {
+        match *self {
+            Collider::Circle { center, radius } => (point - center).norm_squared() < radius * radius,
+            Collider::Aabb { min, max } => min.x < point.x && point.x < max.x && min.y < point.y && point.y < max.y,
+            Collider::Polygon { .. } => {
+                self.hull().edges().all(|(a, _, normal)| (point - a).dot(&normal) < 0.0)
+            },
+            Collider::Capsule { a, b, radius } => (point - closest_on_segment(point, a, b)).norm_squared() < radius * radius,
+        }
+    }
+
+    /// Distance along the unit vector `dir` at which a ray from `origin` enters the collider,
+    /// and the outward normal where it does. A ray starting inside hits at distance 0, facing
+    /// back along the ray.
+    pub fn raycast(&self, origin: Point, dir: Vector) -> Option<(f32, Vector)> {
+        if self.contains(origin) {
+            return Some((0.0, -dir));
+        }
+        match *self {
+            Collider::Circle { center, radius } => ray_circle(origin, dir, center, radius),
+            Collider::Aabb { min, max } => ray_aabb(origin, dir, min, max),
+            Collider::Polygon { .. } => ray_polygon(origin, dir, &self.hull()),
+            Collider::Capsule { a, b, radius: 0.0 } => ray_segment(origin, dir, a, b),
+            Collider::Capsule { a, b, radius } => {
+                // the two round ends and the box between them
+                let middle = if a != b {
+                    let side = perpendicular(b - a).normalize() * radius;
+                    ray_polygon(origin, dir, &Hull::new(&[a + side, b + side, b - side, a - side], 0.0))
+                } else {
+                    None
+                };
+                [ray_circle(origin, dir, a, radius), ray_circle(origin, dir, b, radius), middle]
+                    .iter()
+                    .flatten()
+                    .copied()
+                    .min_by(|x, y| x.0.total_cmp(&y.0))
+            },
+        }
+    }
+
+    /// How far along `motion` the collider gets before it touches `other`, as a fraction of
+    /// `motion` from 0 to 1. None if it doesn't touch `other` on the way, or already overlaps
+    /// it at the start. Circles are swept exactly against everything. Other shapes are swept as
+    /// their bounding box, which is exact against boxes and stops at the bounds of anything
+    /// else.
+    pub fn time_of_impact(&self, motion: Vector, other: &Collider) -> Option<f32> {
+        let length = motion.norm();
+        if length == 0.0 || collide(self, other).is_some() {
+            return None;
+        }
+        let dir = motion / length;
+
+        // sweeping a shape is the same as casting a ray from its centre at the other shape
+        // grown by it
+        let distance = match (*self, *other) {
+            (Collider::Circle { center, radius: r }, Collider::Circle { center: c, radius }) => {
+                Collider::Circle { center: c, radius: radius + r }.raycast(center, dir)
+            },
+            (Collider::Circle { center, radius: r }, Collider::Capsule { a, b, radius }) => {
+                Collider::Capsule { a, b, radius: radius + r }.raycast(center, dir)
+            },
+            (Collider::Circle { center, radius: r }, _) => {
+                // starting outside, the grown outline is first reached on one of the edges
+                let hull = other.hull();
+                let points = hull.points();
+                points.iter()
+                    .zip(points.iter().cycle().skip(1))
+                    .filter_map(|(a, b)| Collider::Capsule { a: *a, b: *b, radius: r }.raycast(center, dir))
+                    .min_by(|x, y| x.0.total_cmp(&y.0))
+            },
+            _ => {
+                let (min, max) = self.bounds();
+                let half = (max - min) / 2.0;
+                let (other_min, other_max) = other.bounds();
+                Collider::Aabb { min: other_min - half, max: other_max + half }.raycast(min + half, dir)
+            },
+        };
+        distance.map(|(distance, _)| distance / length).filter(|t| *t < 1.0)
+    }
+
+    /// The collider as the points of a convex outline, grown by a radius
+    fn hull(&self) -> Hull {
+        match *self {
+            Collider::Circle { center, radius } => Hull::new(&[center], radius),
+            Collider::Aabb { min, max } => Hull::new(&[min, Point::new(max.x, min.y), max, Point::new(min.x, max.y)], 0.0),
+            Collider::Polygon { vertices, count } => Hull::new(&vertices[..count], 0.0),
+            // a capsule without length is a circle, and has no direction to give a side
+            Collider::Capsule { a, b, radius } if a == b => Hull::new(&[a], radius),
+            Collider::Capsule { a, b, radius } => Hull::new(&[a, b], radius),
+        }
+    }
+}
+
+impl ShapeType {
+    /// The box around the shape before it is placed and turned, as (min, max) relative to its
+    /// origin, see `Anchor::Origin`
+    pub fn local_bounds(&self) -> (Point, Point) {
+        match *self {
+            ShapeType::Rectangle(w, h) => (Point::new(0.0, 0.0), Point::new(w, h)),
+            ShapeType::Circle(r) => (Point::new(-r, -r), Point::new(r, r)),
+            ShapeType::Polygon(polygon) => {
+                let vertices = polygon.vertices();
+                let mut min = Point::new(vertices[0].x, vertices[0].y);
+                let mut max = min;
+                for v in &vertices[1..] {
+                    min = Point::new(min.x.min(v.x), min.y.min(v.y));
+                    max = Point::new(max.x.max(v.x), max.y.max(v.y));
+                }
+                (min, max)
+            },
+            ShapeType::Capsule(length, r) => (Point::new(-length / 2.0 - r, -r), Point::new(length / 2.0 + r, r)),
+            ShapeType::Segment(length) => (Point::new(-length / 2.0, 0.0), Point::new(length / 2.0, 0.0)),
+        }
+    }
+}
+
+/// Placing shapes in the world. Everything that needs to know where a shape is or how far it
+/// reaches, from contacts and screen edges to drawing, goes through these.
+impl Shape {
+    /// The point `anchor` picks, relative to the shape's origin
+    pub fn anchor_point(&self, anchor: Anchor) -> Point {
+        match anchor {
+            Anchor::Origin => Point::origin(),
+            Anchor::Bounds(x, y) => {
+                let (min, max) = self.shape_type.local_bounds();
+                min + (max - min).component_mul(&Vector::new(x, y))
+            },
+        }
+    }
+
+    /// The shape with its `anchor` at `position`, turned around it by `angle` radians. Only
+    /// rectangles that aren't turned stay axis aligned boxes, the others become polygons.
+    pub fn collider(&self, position: &Position, angle: f32, anchor: Anchor) -> Collider {
+        let origin = Point::new(position.x, position.y);
+        let offset = self.anchor_point(anchor).coords;
+        let (sin, cos) = angle.sin_cos();
+        let place = |x: f32, y: f32| {
+            let local = Vector::new(x, y) - offset;
+            origin + Vector::new(local.x * cos - local.y * sin, local.x * sin + local.y * cos)
+        };
+        match self.shape_type {
+            ShapeType::Circle(radius) => Collider::Circle { center: place(0.0, 0.0), radius },
+            ShapeType::Rectangle(w, h) if angle == 0.0 => Collider::Aabb {
+                min: place(0.0, 0.0),
+                max: place(w, h),
+            },
+            ShapeType::Rectangle(w, h) => {
+                Collider::polygon(&[place(0.0, 0.0), place(w, 0.0), place(w, h), place(0.0, h)])
+            },
+            ShapeType::Polygon(polygon) => {
+                let mut vertices = [Point::origin(); MAX_POLYGON_VERTICES];
+                for (corner, v) in vertices.iter_mut().zip(polygon.vertices()) {
+                    *corner = place(v.x, v.y);
+                }
+                Collider::Polygon { vertices, count: polygon.vertices().len() }
+            },
+            ShapeType::Capsule(length, radius) => {
+                Collider::Capsule { a: place(-length / 2.0, 0.0), b: place(length / 2.0, 0.0), radius }
+            },
+            ShapeType::Segment(length) => {
+                Collider::Capsule { a: place(-length / 2.0, 0.0), b: place(length / 2.0, 0.0), radius: 0.0 }
+            },
+        }
+    }
+
+    /// Smallest axis aligned box around the placed shape, as (min, max)
+    pub fn aabb(&self, position: &Position, angle: f32, anchor: Anchor) -> (Point, Point) {
+        self.collider(position, angle, anchor).bounds()
+    }
+}
+
+/// A point, a line or a convex polygon, plus everything within `radius` of it. Every collider
+/// can be written as one, which lets any pair of them be tested the same way.
+#[derive(Debug, Copy, Clone)]
+struct Hull {
+    points: [Point; MAX_POLYGON_VERTICES],
+    count: usize,
+    radius: f32,
+}
+
+impl Hull {
+    fn new(points: &[Point], radius: f32) -> Self {
+        let mut hull = Hull { points: [Point::origin(); MAX_POLYGON_VERTICES], count: points.len(), radius };
+        hull.points[..points.len()].copy_from_slice(points);
+        hull
+    }
+
+    fn points(&self) -> &[Point] {
+        &self.points[..self.count]
+    }
+
+    fn center(&self) -> Point {
+        let sum = self.points().iter().fold(Vector::zeros(), |sum, p| sum + p.coords);
+        Point::from(sum / self.count as f32)
+    }
+
+    /// Each side as (start, end, outward normal). A line is a single side facing either way.
+    fn edges(&self) -> impl Iterator<Item = (Point, Point, Vector)> + '_ {
+        let center = self.center();
+        let sides = match self.count {
+            1 => 0,
+            2 => 1,
+            n => n,
+        };
+        (0..sides).map(move |i| {
+            let (a, b) = (self.points[i], self.points[(i + 1) % self.count]);
+            let mut normal = perpendicular(b - a).normalize();
+            if (a - center).dot(&normal) < 0.0 {
+                normal = -normal;
+            }
+            (a, b, normal)
+        })
+    }
+
+    /// Directions to look for a separating gap along. Lines need their own direction as well,
+    /// two of them lying on the same line only come apart along it.
+    fn axes(&self) -> impl Iterator<Item = Vector> + '_ {
+        let along = if self.count == 2 { Some((self.points[1] - self.points[0]).normalize()) } else { None };
+        self.edges().map(|(_, _, normal)| normal).chain(along)
+    }
+
+    /// Smallest and largest extent along `axis`, without the radius
+    fn project(&self, axis: Vector) -> (f32, f32) {
+        self.points().iter().fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), p| {
+            let d = p.coords.dot(&axis);
+            (min.min(d), max.max(d))
+        })
+    }
+
+    /// The point furthest along `dir`, the first one if several are
+    fn support(&self, dir: Vector) -> Point {
+        let mut best = self.points[0];
+        for p in &self.points()[1..] {
+            if p.coords.dot(&dir) > best.coords.dot(&dir) {
+                best = *p;
+            }
+        }
+        best
+    }
+}
+
+fn perpendicular(v: Vector) -> Vector {
+    Vector::new(-v.y, v.x)
+}
+
+pub fn closest_on_segment(point: Point, a: Point, b: Point) -> Point {
+    let ab = b - a;
+    let length_squared = ab.norm_squared();
+    if length_squared == 0.0 {
+        return a;
+    }
+    a + ab * na::clamp((point - a).dot(&ab) / length_squared, 0.0, 1.0)
+}
+
+/// Where two colliders touch
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Contact {
+    /// unit vector pointing from the first collider into the second
+    pub normal: Vector,
+    /// how far the colliders overlap along `normal`
+    pub depth: f32,
+    pub point: Point,
+}
+
+/// A contact between two entities. Separating them means moving `a` along `-normal` and `b`
+/// along `normal`, by `depth` in total.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Manifold {
+    pub a: Entity,
+    pub b: Entity,
+    pub contact: Contact,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TriggerEventKind {
+    /// the shapes started overlapping this step
+    Enter,
+    /// the shapes were overlapping before and still are
+    Stay,
+    /// the shapes stopped overlapping, or one of them was despawned or stopped being a sensor
+    Exit,
+}
+
+/// Reported instead of a contact when a shape overlaps a sensor, see `CollisionFilter`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TriggerEvent {
+    pub kind: TriggerEventKind,
+    pub sensor: Entity,
+    pub other: Entity,
+}
+
+/// Overlap test for any pair of colliders. Colliders that only touch don't count.
+pub fn collide(a: &Collider, b: &Collider) -> Option<Contact> {
+    match (*a, *b) {
+        (Collider::Circle { center: ca, radius: ra }, Collider::Circle { center: cb, radius: rb }) => {
+            circle_circle(ca, ra, cb, rb)
+        },
+        (Collider::Circle { center, radius }, Collider::Aabb { min, max }) => circle_aabb(center, radius, min, max),
+        (Collider::Aabb { min, max }, Collider::Circle { center, radius }) => {
+            circle_aabb(center, radius, min, max).map(flip)
+        },
+        (Collider::Aabb { min: amin, max: amax }, Collider::Aabb { min: bmin, max: bmax }) => {
+            aabb_aabb(amin, amax, bmin, bmax)
+        },
+        _ => hull_hull(&a.hull(), &b.hull()),
+    }
+}
+
+/// Separating axis test for the outlines, and the closest points between them if they are
+/// apart but their radii may still reach each other
+fn hull_hull(a: &Hull, b: &Hull) -> Option<Contact> {
+    // the direction, pointing from a to b, that pushes the outlines apart the least
+    let mut least: Option<(Vector, f32)> = None;
+    for axis in a.axes().chain(b.axes()) {
+        let (amin, amax) = a.project(axis);
+        let (bmin, bmax) = b.project(axis);
+        for (normal, overlap) in [(axis, amax - bmin), (-axis, bmax - amin)] {
+            if least.map(|(_, least)| overlap < least).unwrap_or(true) {
+                least = Some((normal, overlap));
+            }
+        }
+    }
+    let radii = a.radius + b.radius;
+
+    if let Some((normal, overlap)) = least {
+        if overlap > 0.0 {
+            let depth = overlap + radii;
+            // halfway between the deepest points of the two shapes
+            let deepest = b.support(-normal) - normal * b.radius;
+            return Some(Contact { normal, depth, point: deepest + normal * (depth / 2.0) });
+        }
+    }
+    if radii == 0.0 {
+        return None;
+    }
+
+    let (p, q) = closest_points(a, b);
+    let d = q - p;
+    let distance_squared = d.norm_squared();
+    if distance_squared >= radii * radii {
+        return None;
+    }
+    let distance = distance_squared.sqrt();
+    // outlines that touch have no direction between them, take the one the separating axis
+    // test settled on
+    let normal = if distance > 0.0 {
+        d / distance
+    } else {
+        least.map(|(normal, _)| normal).unwrap_or_else(|| Vector::new(1.0, 0.0))
+    };
+    let depth = radii - distance;
+    Some(Contact { normal, depth, point: p + normal * (a.radius - depth / 2.0) })
+}
+
+/// Closest points on two outlines that don't overlap, as (on a, on b). One of them is always a
+/// corner, so trying every corner against every side finds them.
+fn closest_points(a: &Hull, b: &Hull) -> (Point, Point) {
+    let sides = |hull: &Hull| -> Vec<(Point, Point)> {
+        match hull.count {
+            1 => vec![(hull.points[0], hull.points[0])],
+            _ => hull.edges().map(|(start, end, _)| (start, end)).collect(),
+        }
+    };
+    let mut closest = (a.points[0], b.points[0]);
+    let mut distance = f32::INFINITY;
+    let mut consider = |p: Point, q: Point| {
+        let d = (q - p).norm_squared();
+        if d < distance {
+            distance = d;
+            closest = (p, q);
+        }
+    };
+    for (start, end) in sides(b) {
+        for p in a.points() {
+            consider(*p, closest_on_segment(*p, start, end));
+        }
+    }
+    for (start, end) in sides(a) {
+        for q in b.points() {
+            consider(closest_on_segment(*q, start, end), *q);
+        }
+    }
+    closest
+}
+
+fn flip(contact: Contact) -> Contact {
+    Contact { normal: -contact.normal, ..contact }
+}
+
+pub fn circle_circle(ca: Point, ra: f32, cb: Point, rb: f32) -> Option<Contact> {
+    let d = cb - ca;
+    let distance_squared = d.norm_squared();
+    let radii = ra + rb;
+    if distance_squared >= radii * radii {
+        return None;
+    }
+
+    let distance = distance_squared.sqrt();
+    // concentric circles have no preferred direction, pick one so the result is still usable
+    let normal = if distance > 0.0 { d / distance } else { Vector::new(1.0, 0.0) };
+    let depth = radii - distance;
+    Some(Contact { normal, depth, point: ca + normal * (ra - depth / 2.0) })
+}
+
+pub fn circle_aabb(center: Point, radius: f32, min: Point, max: Point) -> Option<Contact> {
+    let closest = Point::new(na::clamp(center.x, min.x, max.x), na::clamp(center.y, min.y, max.y));
+
+    if closest != center {
+        let d = closest - center;
+        let distance_squared = d.norm_squared();
+        if distance_squared >= radius * radius {
+            return None;
+        }
+        let distance = distance_squared.sqrt();
+        return Some(Contact { normal: d / distance, depth: radius - distance, point: closest });
+    }
+
+    // the centre is inside the box, push the circle out through the nearest side
+    let sides = [
+        (center.x - min.x, Vector::new(1.0, 0.0), Point::new(min.x, center.y)),
+        (max.x - center.x, Vector::new(-1.0, 0.0), Point::new(max.x, center.y)),
+        (center.y - min.y, Vector::new(0.0, 1.0), Point::new(center.x, min.y)),
+        (max.y - center.y, Vector::new(0.0, -1.0), Point::new(center.x, max.y)),
+    ];
+    let mut nearest = sides[0];
+    for side in &sides[1..] {
+        if side.0 < nearest.0 {
+            nearest = *side;
+        }
+    }
+    let (distance, normal, point) = nearest;
+    Some(Contact { normal, depth: distance + radius, point })
+}
+
+pub fn aabb_aabb(amin: Point, amax: Point, bmin: Point, bmax: Point) -> Option<Contact> {
+    let overlap_x = amax.x.min(bmax.x) - amin.x.max(bmin.x);
+    let overlap_y = amax.y.min(bmax.y) - amin.y.max(bmin.y);
+    if overlap_x <= 0.0 || overlap_y <= 0.0 {
+        return None;
+    }
+
+    let point = Point::new(
+        (amin.x.max(bmin.x) + amax.x.min(bmax.x)) / 2.0,
+        (amin.y.max(bmin.y) + amax.y.min(bmax.y)) / 2.0,
+    );
+    let a_center = na::center(&amin, &amax);
+    let b_center = na::center(&bmin, &bmax);
+
+    // separate along the axis with the least overlap
+    if overlap_x < overlap_y {
+        let sign = if b_center.x < a_center.x { -1.0 } else { 1.0 };
+        Some(Contact { normal: Vector::new(sign, 0.0), depth: overlap_x, point })
+    } else {
+        let sign = if b_center.y < a_center.y { -1.0 } else { 1.0 };
+        Some(Contact { normal: Vector::new(0.0, sign), depth: overlap_y, point })
+    }
+}
+
+/// Ray against a circle the ray starts outside of, see `Collider::raycast`
+pub fn ray_circle(origin: Point, dir: Vector, center: Point, radius: f32) -> Option<(f32, Vector)> {
+    let m = origin - center;
+    let b = m.dot(&dir);
+    let c = m.norm_squared() - radius * radius;
+    let discriminant = b * b - c;
+    // pointing away, or passing by
+    if b > 0.0 || discriminant < 0.0 {
+        return None;
+    }
+    let t = (-b - discriminant.sqrt()).max(0.0);
+    let normal = (origin + dir * t - center) / radius;
+    Some((t, normal))
+}
+
+/// Ray against a box the ray starts outside of, see `Collider::raycast`
+pub fn ray_aabb(origin: Point, dir: Vector, min: Point, max: Point) -> Option<(f32, Vector)> {
+    let mut enter = 0.0f32;
+    let mut exit = f32::INFINITY;
+    let mut normal = -dir;
+    for axis in 0..2 {
+        if dir[axis] == 0.0 {
+            // parallel to this pair of sides, it has to start between them
+            if origin[axis] < min[axis] || origin[axis] > max[axis] {
+                return None;
+            }
+            continue;
+        }
+        let (near, far, side) = if dir[axis] > 0.0 {
+            ((min[axis] - origin[axis]) / dir[axis], (max[axis] - origin[axis]) / dir[axis], -1.0)
+        } else {
+            ((max[axis] - origin[axis]) / dir[axis], (min[axis] - origin[axis]) / dir[axis], 1.0)
+        };
+        if near >= enter {
+            enter = near;
+            normal = Vector::zeros();
+            normal[axis] = side;
+        }
+        exit = exit.min(far);
+    }
+    if enter > exit {
+        return None;
+    }
+    Some((enter, normal))
+}
+
+/// Ray against a convex polygon the ray starts outside of, see `Collider::raycast`
+fn ray_polygon(origin: Point, dir: Vector, hull: &Hull) -> Option<(f32, Vector)> {
+    let mut enter = 0.0f32;
+    let mut exit = f32::INFINITY;
+    let mut normal = -dir;
+    for (a, _, side) in hull.edges() {
+        // how far outside this side the origin is, and how fast the ray closes in on it
+        let distance = (origin - a).dot(&side);
+        let approach = -dir.dot(&side);
+        if approach == 0.0 {
+            // parallel to the side, it has to start on the inside
+            if distance > 0.0 {
+                return None;
+            }
+            continue;
+        }
+        let t = distance / approach;
+        if approach > 0.0 {
+            if t >= enter {
+                enter = t;
+                normal = side;
+            }
+        } else {
+            exit = exit.min(t);
+        }
+    }
+    if enter > exit {
+        return None;
+    }
+    Some((enter, normal))
+}
+
+/// Ray against a line, hitting the side facing the ray's origin
+pub fn ray_segment(origin: Point, dir: Vector, a: Point, b: Point) -> Option<(f32, Vector)> {
+    let ab = b - a;
+    let denominator = perpendicular(ab).dot(&dir);
+    if denominator == 0.0 {
+        return None;
+    }
+    let t = perpendicular(ab).dot(&(a - origin)) / denominator;
+    let s = perpendicular(dir).dot(&(a - origin)) / denominator;
+    if t < 0.0 || !(0.0..=1.0).contains(&s) {
+        return None;
+    }
+    let normal = perpendicular(ab).normalize();
+    Some((t, if normal.dot(&dir) > 0.0 { -normal } else { normal }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn p(x: f32, y: f32) -> Point {
+        Point::new(x, y)
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn circles() {
+        assert_eq!(circle_circle(p(0.0, 0.0), 1.0, p(2.0, 0.0), 1.0), None);
+
+        let c = circle_circle(p(0.0, 0.0), 2.0, p(3.0, 0.0), 2.0).unwrap();
+        assert_eq!(c.normal, Vector::new(1.0, 0.0));
+        assert_close(c.depth, 1.0);
+        assert_close(c.point.x, 1.5);
+
+        let c = circle_circle(p(1.0, 1.0), 1.0, p(1.0, 1.0), 1.0).unwrap();
+        assert_close(c.depth, 2.0);
+    }
+
+    #[test]
+    fn circle_against_box() {
+        let (min, max) = (p(0.0, 0.0), p(10.0, 10.0));
+        assert_eq!(circle_aabb(p(-3.0, 5.0), 2.0, min, max), None);
+
+        // overlapping the left side from outside
+        let c = circle_aabb(p(-1.0, 5.0), 2.0, min, max).unwrap();
+        assert_eq!(c.normal, Vector::new(1.0, 0.0));
+        assert_close(c.depth, 1.0);
+        assert_eq!(c.point, p(0.0, 5.0));
+
+        // centre inside, closest to the bottom side
+        let c = circle_aabb(p(5.0, 9.0), 2.0, min, max).unwrap();
+        assert_eq!(c.normal, Vector::new(0.0, -1.0));
+        assert_close(c.depth, 3.0);
+        assert_eq!(c.point, p(5.0, 10.0));
+    }
+
+    #[test]
+    fn boxes() {
+        assert_eq!(aabb_aabb(p(0.0, 0.0), p(2.0, 2.0), p(2.0, 0.0), p(4.0, 2.0)), None);
+
+        let c = aabb_aabb(p(0.0, 0.0), p(4.0, 4.0), p(3.0, 1.0), p(7.0, 3.0)).unwrap();
+        assert_eq!(c.normal, Vector::new(1.0, 0.0));
+        assert_close(c.depth, 1.0);
+        assert_eq!(c.point, p(3.5, 2.0));
+
+        let c = aabb_aabb(p(0.0, 0.0), p(4.0, 4.0), p(1.0, -3.0), p(3.0, 1.0)).unwrap();
+        assert_eq!(c.normal, Vector::new(0.0, -1.0));
+        assert_close(c.depth, 1.0);
+    }
+
+    #[test]
+    fn rays() {
+        let right = Vector::new(1.0, 0.0);
+        let (t, n) = ray_circle(p(0.0, 0.0), right, p(10.0, 0.0), 2.0).unwrap();
+        assert_close(t, 8.0);
+        assert_eq!(n, Vector::new(-1.0, 0.0));
+        assert_eq!(ray_circle(p(0.0, 0.0), -right, p(10.0, 0.0), 2.0), None);
+        assert_eq!(ray_circle(p(0.0, 3.0), right, p(10.0, 0.0), 2.0), None);
+
+        let (t, n) = ray_aabb(p(0.0, 5.0), right, p(4.0, 0.0), p(8.0, 10.0)).unwrap();
+        assert_close(t, 4.0);
+        assert_eq!(n, Vector::new(-1.0, 0.0));
+        let (t, n) = ray_aabb(p(5.0, 20.0), Vector::new(0.0, -1.0), p(4.0, 0.0), p(8.0, 10.0)).unwrap();
+        assert_close(t, 10.0);
+        assert_eq!(n, Vector::new(0.0, 1.0));
+        assert_eq!(ray_aabb(p(0.0, 11.0), right, p(4.0, 0.0), p(8.0, 10.0)), None);
+        assert_eq!(ray_aabb(p(10.0, 5.0), right, p(4.0, 0.0), p(8.0, 10.0)), None);
+
+        // starting inside hits straight away
+        let rect = Collider::Aabb { min: p(4.0, 0.0), max: p(8.0, 10.0) };
+        assert_eq!(rect.raycast(p(5.0, 5.0), right), Some((0.0, -right)));
+        assert!(rect.contains(p(5.0, 5.0)));
+        assert!(!rect.contains(p(4.0, 5.0)));
+    }
+
+    #[test]
+    fn collide_keeps_normal_pointing_from_a_to_b() {
+        let circle = Collider::new(&Position { x: -1.0, y: 5.0 }, &Shape { shape_type: ShapeType::Circle(2.0), colour: ggez::graphics::WHITE });
+        let rect = Collider::new(&Position { x: 0.0, y: 0.0 }, &Shape { shape_type: ShapeType::Rectangle(10.0, 10.0), colour: ggez::graphics::WHITE });
+
+        assert_eq!(collide(&circle, &rect).unwrap().normal, Vector::new(1.0, 0.0));
+        assert_eq!(collide(&rect, &circle).unwrap().normal, Vector::new(-1.0, 0.0));
+        assert_eq!(rect.bounds(), (p(0.0, 0.0), p(10.0, 10.0)));
+        assert_eq!(circle.bounds(), (p(-3.0, 3.0), p(1.0, 7.0)));
+    }
+
+    fn shape(shape_type: ShapeType) -> Shape {
+        Shape { shape_type, colour: ggez::graphics::WHITE }
+    }
+
+    fn triangle() -> Collider {
+        let polygon = ConvexPolygon::new(&[(0.0, 0.0), (20.0, 0.0), (0.0, 20.0)]).unwrap();
+        Collider::new(&Position { x: 0.0, y: 0.0 }, &shape(ShapeType::Polygon(polygon)))
+    }
+
+    #[test]
+    fn convex_polygons() {
+        // corners inside the hull or on its edges are dropped
+        let square = ConvexPolygon::new(&[(0.0, 0.0), (10.0, 0.0), (5.0, 0.0), (10.0, 10.0), (5.0, 5.0), (0.0, 10.0)]).unwrap();
+        let corners: Vec<(f32, f32)> = square.vertices().iter().map(|v| (v.x, v.y)).collect();
+        assert_eq!(corners, vec![(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)]);
+        assert_close(square.area(), 100.0);
+
+        assert_eq!(ConvexPolygon::new(&[(0.0, 0.0), (1.0, 1.0), (2.0, 2.0)]), None);
+        assert_eq!(ConvexPolygon::regular(MAX_POLYGON_VERTICES + 1, 10.0), None);
+        assert_eq!(ConvexPolygon::regular(MAX_POLYGON_VERTICES, 10.0).unwrap().vertices().len(), MAX_POLYGON_VERTICES);
+
+        // as a polygon the square collides the same as the box it covers
+        let polygon = Collider::new(&Position { x: 0.0, y: 0.0 }, &shape(ShapeType::Polygon(square)));
+        let rect = Collider::Aabb { min: p(8.0, 2.0), max: p(14.0, 6.0) };
+        let c = collide(&polygon, &rect).unwrap();
+        assert_eq!(c.normal, Vector::new(1.0, 0.0));
+        assert_close(c.depth, 2.0);
+        assert_eq!(polygon.bounds(), (p(0.0, 0.0), p(10.0, 10.0)));
+    }
+
+    #[test]
+    fn rotation() {
+        let rect = shape(ShapeType::Rectangle(10.0, 4.0));
+        let origin = Position { x: 0.0, y: 0.0 };
+        assert_eq!(rect.collider(&origin, 0.0, Anchor::Origin), Collider::new(&origin, &rect));
+
+        // a quarter turn clockwise on screen around the top left corner
+        let (min, max) = rect.aabb(&origin, std::f32::consts::FRAC_PI_2, Anchor::Origin);
+        assert_close(min.x, -4.0);
+        assert_close(min.y, 0.0);
+        assert_close(max.x, 0.0);
+        assert_close(max.y, 10.0);
+
+        let segment = shape(ShapeType::Segment(10.0));
+        let (min, max) = segment.aabb(&Position { x: 5.0, y: 5.0 }, std::f32::consts::FRAC_PI_2, Anchor::Origin);
+        assert_close(min.y, 0.0);
+        assert_close(max.y, 10.0);
+        assert_close(max.x - min.x, 0.0);
+    }
+
+    #[test]
+    fn bounds_of_every_shape() {
+        use std::f32::consts::FRAC_PI_2;
+
+        let triangle = ConvexPolygon::new(&[(0.0, 0.0), (20.0, 0.0), (0.0, 20.0)]).unwrap();
+        // (shape, angle, anchor, min, max) with the anchor at (100, 100)
+        let cases = [
+            (ShapeType::Rectangle(20.0, 10.0), 0.0, Anchor::Origin, p(100.0, 100.0), p(120.0, 110.0)),
+            (ShapeType::Rectangle(20.0, 10.0), 0.0, Anchor::TOP_LEFT, p(100.0, 100.0), p(120.0, 110.0)),
+            (ShapeType::Rectangle(20.0, 10.0), 0.0, Anchor::CENTER, p(90.0, 95.0), p(110.0, 105.0)),
+            (ShapeType::Rectangle(20.0, 10.0), 0.0, Anchor::Bounds(1.0, 1.0), p(80.0, 90.0), p(100.0, 100.0)),
+            (ShapeType::Rectangle(20.0, 10.0), FRAC_PI_2, Anchor::Origin, p(90.0, 100.0), p(100.0, 120.0)),
+            (ShapeType::Rectangle(20.0, 10.0), FRAC_PI_2, Anchor::CENTER, p(95.0, 90.0), p(105.0, 110.0)),
+            (ShapeType::Circle(5.0), 0.0, Anchor::Origin, p(95.0, 95.0), p(105.0, 105.0)),
+            (ShapeType::Circle(5.0), 0.0, Anchor::CENTER, p(95.0, 95.0), p(105.0, 105.0)),
+            (ShapeType::Circle(5.0), 0.0, Anchor::TOP_LEFT, p(100.0, 100.0), p(110.0, 110.0)),
+            (ShapeType::Circle(5.0), FRAC_PI_2, Anchor::Origin, p(95.0, 95.0), p(105.0, 105.0)),
+            (ShapeType::Circle(5.0), FRAC_PI_2, Anchor::TOP_LEFT, p(90.0, 100.0), p(100.0, 110.0)),
+            (ShapeType::Polygon(triangle), 0.0, Anchor::Origin, p(100.0, 100.0), p(120.0, 120.0)),
+            (ShapeType::Polygon(triangle), 0.0, Anchor::CENTER, p(90.0, 90.0), p(110.0, 110.0)),
+            (ShapeType::Polygon(triangle), FRAC_PI_2, Anchor::Origin, p(80.0, 100.0), p(100.0, 120.0)),
+            (ShapeType::Capsule(20.0, 5.0), 0.0, Anchor::Origin, p(85.0, 95.0), p(115.0, 105.0)),
+            (ShapeType::Capsule(20.0, 5.0), 0.0, Anchor::TOP_LEFT, p(100.0, 100.0), p(130.0, 110.0)),
+            (ShapeType::Capsule(20.0, 5.0), FRAC_PI_2, Anchor::Origin, p(95.0, 85.0), p(105.0, 115.0)),
+            (ShapeType::Segment(20.0), 0.0, Anchor::Origin, p(90.0, 100.0), p(110.0, 100.0)),
+            (ShapeType::Segment(20.0), 0.0, Anchor::TOP_LEFT, p(100.0, 100.0), p(120.0, 100.0)),
+            (ShapeType::Segment(20.0), FRAC_PI_2, Anchor::Origin, p(100.0, 90.0), p(100.0, 110.0)),
+        ];
+        for (shape_type, angle, anchor, min, max) in cases.iter() {
+            let (found_min, found_max) = shape(*shape_type).aabb(&Position { x: 100.0, y: 100.0 }, *angle, *anchor);
+            for (found, expected) in [(found_min, min), (found_max, max)] {
+                assert!(
+                    (found - expected).norm() < 1e-4,
+                    "{:?} turned by {} anchored at {:?}: {} != {}", shape_type, angle, anchor, found, expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn polygon_contacts() {
+        let triangle = triangle();
+
+        // a circle just above the top side
+        let circle = Collider::Circle { center: p(10.0, -3.0), radius: 4.0 };
+        let c = collide(&circle, &triangle).unwrap();
+        assert_eq!(c.normal, Vector::new(0.0, 1.0));
+        assert_close(c.depth, 1.0);
+        assert_eq!(c.point, p(10.0, 0.5));
+        assert_eq!(collide(&triangle, &circle).unwrap().normal, Vector::new(0.0, -1.0));
+        assert_eq!(collide(&Collider::Circle { center: p(10.0, -4.0), radius: 4.0 }, &triangle), None);
+
+        // centre inside, pushed out through the nearest side
+        let c = collide(&Collider::Circle { center: p(2.0, 10.0), radius: 1.0 }, &triangle).unwrap();
+        assert_eq!(c.normal, Vector::new(1.0, 0.0));
+        assert_close(c.depth, 3.0);
+
+        // past the long side, which faces down and to the right
+        assert_eq!(collide(&Collider::Aabb { min: p(11.0, 11.0), max: p(20.0, 20.0) }, &triangle), None);
+        let c = collide(&Collider::Aabb { min: p(9.0, 9.0), max: p(20.0, 20.0) }, &triangle).unwrap();
+        assert_close(c.normal.x, -std::f32::consts::FRAC_1_SQRT_2);
+        assert_close(c.normal.y, -std::f32::consts::FRAC_1_SQRT_2);
+    }
+
+    #[test]
+    fn capsules_and_segments() {
+        let floor = Collider::new(&Position { x: 50.0, y: 100.0 }, &shape(ShapeType::Segment(100.0)));
+        let c = collide(&Collider::Circle { center: p(50.0, 97.0), radius: 4.0 }, &floor).unwrap();
+        assert_eq!(c.normal, Vector::new(0.0, 1.0));
+        assert_close(c.depth, 1.0);
+        assert_eq!(collide(&Collider::Circle { center: p(101.0, 100.0), radius: 1.0 }, &floor), None);
+
+        // segments only collide when they cross
+        let vertical = shape(ShapeType::Segment(10.0)).collider(&Position { x: 50.0, y: 100.0 }, std::f32::consts::FRAC_PI_2, Anchor::Origin);
+        assert!(collide(&floor, &vertical).is_some());
+        let next = Collider::new(&Position { x: 150.0, y: 100.0 }, &shape(ShapeType::Segment(100.0)));
+        assert_eq!(collide(&floor, &next), None);
+
+        let capsule = Collider::new(&Position { x: 50.0, y: 50.0 }, &shape(ShapeType::Capsule(20.0, 5.0)));
+        assert_eq!(capsule.bounds(), (p(35.0, 45.0), p(65.0, 55.0)));
+        let c = collide(&capsule, &Collider::Aabb { min: p(45.0, 53.0), max: p(55.0, 70.0) }).unwrap();
+        assert_eq!(c.normal, Vector::new(0.0, 1.0));
+        assert_close(c.depth, 2.0);
+        let c = collide(&capsule, &Collider::Circle { center: p(68.0, 50.0), radius: 4.0 }).unwrap();
+        assert_eq!(c.normal, Vector::new(1.0, 0.0));
+        assert_close(c.depth, 1.0);
+    }
+
+    #[test]
+    fn rays_against_the_other_shapes() {
+        let down = Vector::new(0.0, 1.0);
+        let triangle = triangle();
+        assert_eq!(triangle.raycast(p(5.0, -10.0), down), Some((10.0, Vector::new(0.0, -1.0))));
+        let (t, n) = triangle.raycast(p(30.0, 5.0), Vector::new(-1.0, 0.0)).unwrap();
+        assert_close(t, 15.0);
+        assert_close(n.x, std::f32::consts::FRAC_1_SQRT_2);
+        assert_close(n.y, std::f32::consts::FRAC_1_SQRT_2);
+        assert_eq!(triangle.raycast(p(30.0, 30.0), Vector::new(1.0, 0.0)), None);
+        assert!(triangle.contains(p(5.0, 5.0)));
+        assert!(!triangle.contains(p(0.0, 5.0)));
+
+        let floor = Collider::new(&Position { x: 50.0, y: 100.0 }, &shape(ShapeType::Segment(100.0)));
+        assert_eq!(floor.raycast(p(50.0, 0.0), down), Some((100.0, -down)));
+        assert_eq!(floor.raycast(p(50.0, 200.0), -down), Some((100.0, down)));
+        assert_eq!(floor.raycast(p(150.0, 0.0), down), None);
+        assert_eq!(floor.raycast(p(0.0, 0.0), Vector::new(1.0, 0.0)), None);
+        assert!(!floor.contains(p(50.0, 100.0)));
+
+        let capsule = Collider::new(&Position { x: 50.0, y: 50.0 }, &shape(ShapeType::Capsule(20.0, 5.0)));
+        assert_eq!(capsule.raycast(p(0.0, 50.0), Vector::new(1.0, 0.0)), Some((35.0, Vector::new(-1.0, 0.0))));
+        assert_eq!(capsule.raycast(p(50.0, 0.0), down), Some((45.0, -down)));
+        assert!(capsule.contains(p(40.0, 53.0)));
+        assert!(!capsule.contains(p(40.0, 56.0)));
+    }
+
+    #[test]
+    fn time_of_impact() {
+        let right = Vector::new(40.0, 0.0);
+        let ball = Collider::Circle { center: p(0.0, 0.0), radius: 5.0 };
+        let wall = Collider::Aabb { min: p(20.0, -50.0), max: p(22.0, 50.0) };
+        assert_eq!(ball.time_of_impact(right, &wall), Some(0.375));
+        assert_eq!(ball.time_of_impact(right / 4.0, &wall), None);
+        assert_eq!(ball.time_of_impact(-right, &wall), None);
+        assert_eq!(ball.time_of_impact(Vector::zeros(), &wall), None);
+        // already overlapping is left to the contacts
+        let touching = Collider::Circle { center: p(16.0, 0.0), radius: 5.0 };
+        assert_eq!(touching.time_of_impact(right, &wall), None);
+
+        // a corner of the box is reached on the round part of the grown outline
+        let corner = Collider::Aabb { min: p(20.0, 3.0), max: p(30.0, 10.0) };
+        assert_close(ball.time_of_impact(right, &corner).unwrap(), 0.4);
+        let other = Collider::Circle { center: p(30.0, 0.0), radius: 5.0 };
+        assert_eq!(ball.time_of_impact(right, &other), Some(0.5));
+        let post = Collider::new(&Position { x: 30.0, y: 0.0 }, &shape(ShapeType::Capsule(20.0, 1.0)));
+        assert_eq!(ball.time_of_impact(right, &post), Some(0.35));
+        let from_the_right = Collider::Circle { center: p(40.0, 5.0), radius: 5.0 };
+        let hit = from_the_right.time_of_impact(-right, &triangle()).unwrap();
+        assert_close(hit, (25.0 - 5.0 * std::f32::consts::SQRT_2) / 40.0);
+
+        // boxes are swept as boxes
+        let block = Collider::Aabb { min: p(-4.0, -4.0), max: p(4.0, 4.0) };
+        assert_eq!(block.time_of_impact(right, &wall), Some(0.4));
+        assert_eq!(block.time_of_impact(right, &other), Some(0.525));
+        assert_eq!(block.time_of_impact(Vector::new(0.0, 40.0), &wall), None);
+    }
+}
@@ -0,0 +1,40 @@
+use crate::world::*;
+
+type Command = Box<dyn FnOnce(&mut World)>;
+
+/// Queue of world changes recorded while the world is being iterated,
+/// applied later by `World::apply_commands`
+#[derive(Default)]
+pub struct Commands {
+    queue: Vec<Command>,
+}
+
+impl Commands {
+    pub fn new() -> Self {
+        Commands { queue: Vec::new() }
+    }
+
+    pub fn set<T: Component>(&mut self, e: Entity, value: T) {
+        self.queue.push(Box::new(move |world: &mut World| world.set(e, value)));
+    }
+
+    pub fn remove<T: Component>(&mut self, e: Entity) {
+        self.queue.push(Box::new(move |world: &mut World| {
+            world.remove::<T>(e);
+        }));
+    }
+
+    pub fn despawn(&mut self, e: Entity) {
+        self.queue.push(Box::new(move |world: &mut World| {
+            world.despawn(e);
+        }));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    pub fn take(&mut self) -> Vec<Command> {
+        std::mem::take(&mut self.queue)
+    }
+}
@@ -1,25 +1,494 @@
-use ggez::graphics::Color;
+use std::any::Any;
+use std::collections::HashMap;
 
-#[derive(Debug, Copy, Clone, Default, PartialEq)]
+use ggez::graphics::{Color, Rect, WHITE};
+
+use crate::images::ImageHandle;
+use crate::reflect::{FieldInfo, Reflect};
+use crate::world::Entity;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Reflect)]
 pub struct Position {
     pub x: f32,
     pub y: f32,
 }
 
-#[derive(Debug, Copy, Clone, Default, PartialEq)]
+/// Position at the start of the last simulation step, used to interpolate drawing between steps
+#[derive(Debug, Copy, Clone, Default, PartialEq, Reflect)]
+pub struct PreviousPosition {
+    pub x: f32,
+    pub y: f32,
+}
+
+/// In pixels per second
+#[derive(Debug, Copy, Clone, Default, PartialEq, Reflect)]
 pub struct Velocity {
     pub xv: f32,
     pub yv: f32,
 }
 
+/// Forces acting on a dynamic body for the next step, in mass times pixels per second squared.
+/// Add to it from code, it is cleared after every step.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Reflect)]
+pub struct Force {
+    pub fx: f32,
+    pub fy: f32,
+}
+
+impl Force {
+    pub fn add(&mut self, fx: f32, fy: f32) {
+        self.fx += fx;
+        self.fy += fy;
+    }
+}
+
+/// Angle in radians the entity's shape is turned by around its `Position`, clockwise on screen
+/// the same as ggez's `DrawParam::rotation`. Circles look and collide the same at any angle.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Reflect)]
+pub struct Rotation {
+    pub angle: f32,
+}
+
+/// Which point of an entity's shape sits at its `Position`. The shape is turned around it by
+/// its `Rotation`, and drawn and collided the same way.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Reflect)]
+pub enum Anchor {
+    /// the shape's own origin: the top left corner of a rectangle, the centre of a circle,
+    /// capsule or segment, and (0, 0) for the corners of a polygon
+    #[default]
+    Origin,
+    /// a point of the box around the shape before it is turned, as a fraction of its size.
+    /// (0, 0) is the top left corner and (1, 1) the bottom right one.
+    Bounds(f32, f32),
+}
+
+impl Anchor {
+    pub const TOP_LEFT: Anchor = Anchor::Bounds(0.0, 0.0);
+    pub const CENTER: Anchor = Anchor::Bounds(0.5, 0.5);
+}
+
+/// A corner of a `ConvexPolygon`, relative to the entity's `Position`
+#[derive(Debug, Copy, Clone, Default, PartialEq, Reflect)]
+pub struct Vertex {
+    pub x: f32,
+    pub y: f32,
+}
+
+/// Polygons have a fixed number of slots for their corners so shapes stay `Copy`
+pub const MAX_POLYGON_VERTICES: usize = 8;
+
+const VERTEX_NAMES: [&str; MAX_POLYGON_VERTICES] = ["0", "1", "2", "3", "4", "5", "6", "7"];
+
+/// A convex polygon with up to `MAX_POLYGON_VERTICES` corners, going around the outline
+/// clockwise on screen
 #[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ConvexPolygon {
+    vertices: [Vertex; MAX_POLYGON_VERTICES],
+    count: usize,
+}
+
+impl ConvexPolygon {
+    /// The convex hull of `points`, dropping corners inside it or on its edges. `None` if the
+    /// hull is flat, has more than `MAX_POLYGON_VERTICES` corners or a point isn't finite.
+    pub fn new(points: &[(f32, f32)]) -> Option<Self> {
+        if points.iter().any(|(x, y)| !x.is_finite() || !y.is_finite()) {
+            return None;
+        }
+        let mut points = points.to_vec();
+        points.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1)));
+        points.dedup();
+
+        // Andrew's monotone chain, the top half from left to right and the bottom half back
+        let mut hull = hull_chain(points.iter());
+        hull.extend(hull_chain(points.iter().rev()));
+
+        if hull.len() < 3 || hull.len() > MAX_POLYGON_VERTICES {
+            return None;
+        }
+        let mut vertices = [Vertex::default(); MAX_POLYGON_VERTICES];
+        for (vertex, (x, y)) in vertices.iter_mut().zip(&hull) {
+            *vertex = Vertex { x: *x, y: *y };
+        }
+        Some(ConvexPolygon { vertices, count: hull.len() })
+    }
+
+    /// A polygon with `sides` equal sides and its corners `radius` away from the origin, the
+    /// first one straight to the right
+    pub fn regular(sides: usize, radius: f32) -> Option<Self> {
+        let points: Vec<(f32, f32)> = (0..sides)
+            .map(|i| {
+                let angle = i as f32 * std::f32::consts::PI * 2.0 / sides as f32;
+                (radius * angle.cos(), radius * angle.sin())
+            })
+            .collect();
+        ConvexPolygon::new(&points)
+    }
+
+    pub fn vertices(&self) -> &[Vertex] {
+        &self.vertices[..self.count]
+    }
+
+    pub fn area(&self) -> f32 {
+        let vertices = self.vertices();
+        let twice_area: f32 = vertices.iter()
+            .zip(vertices.iter().cycle().skip(1))
+            .map(|(a, b)| a.x * b.y - b.x * a.y)
+            .sum();
+        twice_area.abs() / 2.0
+    }
+}
+
+/// Half of the convex hull of `points`, which must be sorted, keeping only strict turns. The last
+/// point is left off because it starts the other half.
+fn hull_chain<'a>(points: impl Iterator<Item = &'a (f32, f32)>) -> Vec<(f32, f32)> {
+    let cross = |o: (f32, f32), a: (f32, f32), b: (f32, f32)| (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0);
+    let mut chain: Vec<(f32, f32)> = Vec::new();
+    for p in points {
+        while chain.len() >= 2 && cross(chain[chain.len() - 2], chain[chain.len() - 1], *p) <= 0.0 {
+            chain.pop();
+        }
+        chain.push(*p);
+    }
+    chain.pop();
+    chain
+}
+
+// the corners are listed by index, only as many as the polygon has
+impl Reflect for ConvexPolygon {
+    fn type_name(&self) -> &'static str {
+        "ConvexPolygon"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn fields(&self) -> Vec<FieldInfo> {
+        VERTEX_NAMES[..self.count].iter().map(|name| FieldInfo { name, type_name: "Vertex" }).collect()
+    }
+
+    fn visit_fields(&self, visit: &mut dyn FnMut(&'static str, &dyn Reflect)) {
+        for (name, vertex) in VERTEX_NAMES.iter().zip(self.vertices()) {
+            visit(name, vertex);
+        }
+    }
+
+    fn field(&self, name: &str) -> Option<&dyn Reflect> {
+        let i = VERTEX_NAMES[..self.count].iter().position(|n| *n == name)?;
+        Some(&self.vertices[i])
+    }
+
+    fn field_mut(&mut self, name: &str) -> Option<&mut dyn Reflect> {
+        let i = VERTEX_NAMES[..self.count].iter().position(|n| *n == name)?;
+        Some(&mut self.vertices[i])
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Reflect)]
 pub enum ShapeType {
     Rectangle(f32, f32),
     Circle(f32),
+    /// corners relative to `Position`
+    Polygon(ConvexPolygon),
+    /// (length, radius), a `length` long line along the x axis centred on `Position`, with
+    /// everything within `radius` of it
+    Capsule(f32, f32),
+    /// a line of the given length along the x axis, centred on `Position`. It has no area, so
+    /// it is best used for static and kinematic bodies.
+    Segment(f32),
 }
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+impl ShapeType {
+    pub fn area(&self) -> f32 {
+        match *self {
+            ShapeType::Rectangle(w, h) => w * h,
+            ShapeType::Circle(r) => std::f32::consts::PI * r * r,
+            ShapeType::Polygon(polygon) => polygon.area(),
+            ShapeType::Capsule(length, r) => length * r * 2.0 + std::f32::consts::PI * r * r,
+            ShapeType::Segment(_) => 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Reflect)]
 pub struct Shape {
     pub shape_type: ShapeType,
     pub colour: Color,
 }
+
+/// Which pass an entity is drawn in. The layers are drawn in the order they are listed, each
+/// one over the ones before it, and can be hidden one at a time, see `RenderLayers`. Entities
+/// without one are drawn in `World`.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Reflect)]
+pub enum RenderLayer {
+    Background,
+    #[default]
+    World,
+    /// drawn over the world, for things like contact points and bounds
+    Debug,
+    /// drawn in screen pixels, the camera doesn't move it
+    Ui,
+}
+
+impl RenderLayer {
+    pub const ALL: [RenderLayer; 4] = [RenderLayer::Background, RenderLayer::World, RenderLayer::Debug, RenderLayer::Ui];
+}
+
+/// Draw order within a `RenderLayer`, higher is drawn on top. Entities with the same one are
+/// grouped by what they are drawn with so they can be batched, shapes first and then sprites
+/// by image, and then drawn in entity order.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Reflect)]
+pub struct ZIndex(pub i32);
+
+/// An image drawn at the entity's `Position`, placed by its `Anchor` and turned by its
+/// `Rotation` the same way as a rectangle. It is drawn instead of the entity's `Shape`, which
+/// then only matters for collisions.
+#[derive(Debug, Copy, Clone, PartialEq, Reflect)]
+pub struct Sprite {
+    pub image: ImageHandle,
+    /// part of the image that is drawn, in fractions of its size the same as ggez's
+    /// `DrawParam::src`, for picking a frame out of a sprite sheet
+    pub source: Rect,
+    /// multiplies the colours of the image
+    pub tint: Color,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    /// world units per pixel of the image
+    pub scale: f32,
+}
+
+impl Sprite {
+    /// The whole image, as it is
+    pub fn new(image: ImageHandle) -> Self {
+        Sprite { image, source: Rect::new(0.0, 0.0, 1.0, 1.0), tint: WHITE, flip_x: false, flip_y: false, scale: 1.0 }
+    }
+
+    /// Draws the frame at `column` and `row` of a sprite sheet made of `columns` by `rows`
+    /// frames of the same size
+    pub fn frame(mut self, columns: u32, rows: u32, column: u32, row: u32) -> Self {
+        let (w, h) = (1.0 / columns as f32, 1.0 / rows as f32);
+        self.source = Rect::new(column as f32 * w, row as f32 * h, w, h);
+        self
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Reflect)]
+pub enum BodyType {
+    /// never moves, e.g. walls and obstacles
+    Static,
+    /// moved by setting its `Velocity` or `Position` from code, pushes dynamic bodies but is
+    /// not pushed back and ignores gravity
+    Kinematic,
+    /// moved by the simulation, collides with everything
+    Dynamic,
+}
+
+/// Dynamic bodies lighter than this are clamped to it, so their inverse mass stays finite
+pub const MIN_MASS: f32 = 0.001;
+
+/// Marks an entity as taking part in the physics simulation. Entities without one are left
+/// alone by movement and collision response.
+#[derive(Debug, Copy, Clone, PartialEq, Reflect)]
+pub struct RigidBody {
+    pub body_type: BodyType,
+    /// 0 for static and kinematic bodies, which behave as if their mass were infinite
+    mass: f32,
+}
+
+impl RigidBody {
+    pub fn fixed() -> Self {
+        RigidBody { body_type: BodyType::Static, mass: 0.0 }
+    }
+
+    pub fn kinematic() -> Self {
+        RigidBody { body_type: BodyType::Kinematic, mass: 0.0 }
+    }
+
+    /// Masses below `MIN_MASS`, including zero, negative and NaN ones, are clamped to it
+    pub fn dynamic(mass: f32) -> Self {
+        RigidBody { body_type: BodyType::Dynamic, mass: mass.max(MIN_MASS) }
+    }
+
+    /// A dynamic body weighing the shape's area times the material's density
+    pub fn dynamic_from_shape(shape_type: &ShapeType, material: &PhysicsMaterial) -> Self {
+        RigidBody::dynamic(shape_type.area() * material.density)
+    }
+
+    pub fn mass(&self) -> f32 {
+        match self.body_type {
+            BodyType::Dynamic => self.mass.max(MIN_MASS),
+            _ => 0.0,
+        }
+    }
+
+    /// 0 for bodies that are not pushed around. Derived from the mass, so editing the mass
+    /// through reflection can't leave the two out of sync.
+    pub fn inverse_mass(&self) -> f32 {
+        match self.body_type {
+            BodyType::Dynamic => 1.0 / self.mass(),
+            _ => 0.0,
+        }
+    }
+
+    pub fn is_dynamic(&self) -> bool {
+        self.body_type == BodyType::Dynamic
+    }
+
+    /// Dynamic and kinematic bodies move, static ones don't
+    pub fn is_moving(&self) -> bool {
+        self.body_type != BodyType::Static
+    }
+}
+
+/// Lets a dynamic body fall asleep once it has been almost still for a while, see
+/// `PhysicsSettings`. A sleeping body isn't moved and acts like a static one until it is hit
+/// by something moving or a force is applied to it.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Reflect)]
+pub struct Sleep {
+    pub asleep: bool,
+    /// seconds the body has been below the sleep speed, including the time it spent asleep
+    pub idle_time: f32,
+}
+
+impl Sleep {
+    pub fn wake(&mut self) {
+        self.asleep = false;
+        self.idle_time = 0.0;
+    }
+}
+
+/// Continuous collision detection for a fast dynamic body. Instead of jumping straight to
+/// where its velocity takes it, the body is swept there and stops where it first touches a
+/// shape it collides with, so it can't pass through anything thinner than one step of its
+/// motion. Costs a shape query per step, only worth it for small fast bodies.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Reflect)]
+pub struct Ccd;
+
+/// Which shapes an entity's shape collides with. Two shapes only interact if each one's `mask`
+/// contains a layer of the other. Entities without one are on `DEFAULT_LAYER` and collide with
+/// everything.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Reflect)]
+pub struct CollisionFilter {
+    /// layers the shape is on, one bit each
+    pub layers: u32,
+    /// layers the shape collides with
+    pub mask: u32,
+    /// sensors report overlaps as `TriggerEvent`s instead of pushing anything
+    pub sensor: bool,
+}
+
+impl CollisionFilter {
+    pub const DEFAULT_LAYER: u32 = 1;
+    pub const ALL_LAYERS: u32 = u32::MAX;
+
+    pub fn new(layers: u32, mask: u32) -> Self {
+        CollisionFilter { layers, mask, sensor: false }
+    }
+
+    /// A sensor on `layers` noticing shapes on `mask`
+    pub fn sensor(layers: u32, mask: u32) -> Self {
+        CollisionFilter { layers, mask, sensor: true }
+    }
+
+    pub fn interacts(&self, other: &CollisionFilter) -> bool {
+        self.layers & other.mask != 0 && other.layers & self.mask != 0
+    }
+}
+
+impl Default for CollisionFilter {
+    fn default() -> Self {
+        CollisionFilter::new(CollisionFilter::DEFAULT_LAYER, CollisionFilter::ALL_LAYERS)
+    }
+}
+
+/// Connects two bodies. A joint lives on an entity of its own, so a body can have any number
+/// of them, and is despawned once either body is gone.
+#[derive(Debug, Copy, Clone, PartialEq, Reflect)]
+pub struct Joint {
+    pub a: Entity,
+    pub b: Entity,
+    /// where the joint is attached to `a`, relative to its `Position` and turned with its
+    /// `Rotation`
+    pub anchor_a: Vertex,
+    /// the same for `b`
+    pub anchor_b: Vertex,
+    pub kind: JointKind,
+}
+
+impl Joint {
+    /// A joint attached to the positions of both bodies
+    pub fn new(a: Entity, b: Entity, kind: JointKind) -> Self {
+        Joint { a, b, anchor_a: Vertex::default(), anchor_b: Vertex::default(), kind }
+    }
+
+    /// Points the joint at the bodies' new handles, e.g. after they were loaded into another
+    /// world. `false` if either body has none, and the joint should go.
+    pub fn map_entities(&mut self, map: &HashMap<Entity, Entity>) -> bool {
+        match (map.get(&self.a), map.get(&self.b)) {
+            (Some(a), Some(b)) => {
+                self.a = *a;
+                self.b = *b;
+                true
+            },
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Reflect)]
+pub enum JointKind {
+    /// keeps the anchors `length` apart like a rod. A `rope` only keeps them from getting
+    /// further apart than that.
+    Distance { length: f32, rope: bool },
+    /// pulls the anchors towards `rest_length` apart with `stiffness` times how far off they
+    /// are, in mass times pixels per second squared per pixel, and slows them down by
+    /// `damping` times the speed they move apart at
+    Spring { rest_length: f32, stiffness: f32, damping: f32 },
+    /// holds the anchors together, leaving the bodies free to swing around the shared point
+    Pin,
+}
+
+/// How a body behaves in collisions. Bodies without one use the default material.
+#[derive(Debug, Copy, Clone, PartialEq, Reflect)]
+pub struct PhysicsMaterial {
+    /// bounciness, 0 stops dead and 1 bounces back at full speed
+    pub restitution: f32,
+    /// share of the contact impulse that works against sliding
+    pub friction: f32,
+    /// mass per unit of area
+    pub density: f32,
+}
+
+impl Default for PhysicsMaterial {
+    fn default() -> Self {
+        PhysicsMaterial {
+            restitution: 0.6,
+            friction: 0.2,
+            density: 1.0,
+        }
+    }
+}
+
+/// Human readable label for an entity, used by lookups and debug output
+#[derive(Debug, Clone, Default, PartialEq, Reflect)]
+pub struct Name(pub String);
+
+impl Name {
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        Name(name.into())
+    }
+}
+
+// Tag components carry no data, they only mark an entity so it can be picked out with `With<T>`
+
+/// Marks the particles spawned by `generate_entities`
+#[derive(Debug, Copy, Clone, Default, PartialEq, Reflect)]
+pub struct Particle;
+
+/// Marks entities that never move
+#[derive(Debug, Copy, Clone, Default, PartialEq, Reflect)]
+pub struct Obstacle;
@@ -1,6 +1,8 @@
 use std::collections::VecDeque;
 
-#[derive(Debug, Copy, Clone, Default, PartialEq)]
+use crate::reflect::Reflect;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash, Reflect)]
 pub struct GenerationalIndex {
     pub index: usize,
     pub generation: u64,
@@ -127,6 +129,15 @@ impl<T> GenerationalIndexArray<T> {
         }
     }
 
+    pub fn remove(&mut self, index: GenerationalIndex) -> Option<T> {
+        match self.0.get(index.index) {
+            Some(Some(entry)) if entry.generation == index.generation => {
+                self.0[index.index].take().map(|entry| entry.value)
+            },
+            _ => None,
+        }
+    }
+
     pub fn get_mut(&mut self, index: GenerationalIndex) -> Option<&mut T> {
         if let Some(e) = self.0.get_mut(index.index) {
             match e {
@@ -212,4 +223,19 @@ mod tests {
         assert_eq!(1, e_vec[1].index);
         assert_eq!(1, e_vec[1].generation);
     }
+
+    #[test]
+    fn array_remove_checks_generation() {
+        let mut a = GenerationalIndexAllocator::new();
+        let mut arr = GenerationalIndexArray::new();
+        let e1 = a.allocate();
+        arr.set(e1, 10);
+
+        a.deallocate(e1);
+        let e2 = a.allocate();
+
+        assert_eq!(arr.remove(e2), None);
+        assert_eq!(arr.remove(e1), Some(10));
+        assert_eq!(arr.get(e1), None);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,44 @@
+use std::any::TypeId;
+use std::rc::Rc;
+
+use crate::world::*;
+
+pub type Hook = Rc<dyn Fn(&mut World, Entity)>;
+pub type Observer = Rc<dyn Fn(&mut World, &ComponentEvent)>;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ComponentEventKind {
+    /// the entity did not have the component before
+    Added,
+    /// the component was set, either for the first time or replacing an old value
+    Inserted,
+    /// the component is about to be removed, either directly or because the entity is despawned
+    Removed,
+}
+
+/// Passed to entity observers whenever one of the entity's components changes
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ComponentEvent {
+    pub entity: Entity,
+    pub component: TypeId,
+    pub component_name: &'static str,
+    pub kind: ComponentEventKind,
+}
+
+/// Callbacks registered for a single component type
+#[derive(Default, Clone)]
+pub struct ComponentHooks {
+    pub on_add: Vec<Hook>,
+    pub on_insert: Vec<Hook>,
+    pub on_remove: Vec<Hook>,
+}
+
+impl ComponentHooks {
+    pub fn get(&self, kind: ComponentEventKind) -> &[Hook] {
+        match kind {
+            ComponentEventKind::Added => &self.on_add,
+            ComponentEventKind::Inserted => &self.on_insert,
+            ComponentEventKind::Removed => &self.on_remove,
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+use crate::reflect::Reflect;
+
+/// Refers to an image in `Images`
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Reflect)]
+pub struct ImageHandle(pub u32);
+
+/// An image kept on the CPU, 8 bit RGBA row by row from the top left
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageData {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl ImageData {
+    /// `pixels` must hold `width * height` RGBA pixels, and there has to be at least one
+    pub fn new(width: usize, height: usize, pixels: Vec<u8>) -> Self {
+        assert!(width > 0 && height > 0, "{}x{} image is empty", width, height);
+        assert_eq!(pixels.len(), width * height * 4, "{}x{} RGBA image", width, height);
+        ImageData { width, height, pixels }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> [u8; 4] {
+        let i = (y * self.width + x) * 4;
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2], self.pixels[i + 3]]
+    }
+}
+
+/// Images sprites are drawn with, stored on the `World`. Images are never removed, so a handle
+/// stays good for as long as the world is around.
+#[derive(Debug, Clone, Default)]
+pub struct Images {
+    images: Vec<ImageData>,
+}
+
+impl Images {
+    pub fn add(&mut self, image: ImageData) -> ImageHandle {
+        self.images.push(image);
+        ImageHandle(self.images.len() as u32 - 1)
+    }
+
+    /// Reads a PNG file. Greyscale, RGB and palette images are turned into RGBA.
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) -> io::Result<ImageHandle> {
+        let invalid = |e| io::Error::new(io::ErrorKind::InvalidData, e);
+        let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let (info, mut reader) = decoder.read_info().map_err(invalid)?;
+        if info.width == 0 || info.height == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "image is empty"));
+        }
+        let mut buffer = vec![0; info.buffer_size()];
+        reader.next_frame(&mut buffer).map_err(invalid)?;
+
+        let pixels = match info.color_type {
+            png::ColorType::RGBA => buffer,
+            png::ColorType::RGB => buffer.chunks(3).flat_map(|p| vec![p[0], p[1], p[2], 255]).collect(),
+            png::ColorType::GrayscaleAlpha => buffer.chunks(2).flat_map(|p| vec![p[0], p[0], p[0], p[1]]).collect(),
+            png::ColorType::Grayscale => buffer.iter().flat_map(|g| vec![*g, *g, *g, 255]).collect(),
+            png::ColorType::Indexed => return Err(io::Error::new(io::ErrorKind::InvalidData, "palette was not expanded")),
+        };
+        Ok(self.add(ImageData::new(info.width as usize, info.height as usize, pixels)))
+    }
+
+    pub fn get(&self, handle: ImageHandle) -> Option<&ImageData> {
+        self.images.get(handle.0 as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufWriter;
+
+    #[test]
+    fn loads_png_files_as_rgba() {
+        let path = std::env::temp_dir().join(format!("ggez-test2-images-{}.png", std::process::id()));
+        let mut encoder = png::Encoder::new(BufWriter::new(File::create(&path).unwrap()), 2, 1);
+        encoder.set_color(png::ColorType::RGB);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header().unwrap().write_image_data(&[10, 20, 30, 40, 50, 60]).unwrap();
+
+        let mut images = Images::default();
+        let first = images.add(ImageData::new(1, 1, vec![1, 2, 3, 4]));
+        let loaded = images.load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_ne!(first, loaded);
+        let image = images.get(loaded).unwrap();
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!(image.pixel(1, 0), [40, 50, 60, 255]);
+        assert_eq!(images.get(first).unwrap().pixel(0, 0), [1, 2, 3, 4]);
+        assert_eq!(images.get(ImageHandle(7)), None);
+        assert!(images.load("does/not/exist.png").is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "0x3 image is empty")]
+    fn images_are_never_empty() {
+        ImageData::new(0, 3, Vec::new());
+    }
+}
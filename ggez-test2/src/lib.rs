@@ -0,0 +1,22 @@
+pub mod gendex;
+pub mod broadphase;
+pub mod camera;
+pub mod components;
+pub mod hooks;
+pub mod images;
+pub mod commands;
+pub mod checksum;
+pub mod collision;
+pub mod reflect;
+pub mod render;
+pub mod physics;
+pub mod replay;
+pub mod rng;
+pub mod spatial;
+pub mod timestep;
+pub mod world;
+pub mod systems;
+pub mod simulation;
+#[cfg(test)]
+mod snapshot;
+pub mod software;
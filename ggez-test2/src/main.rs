@@ -1,215 +1,159 @@
-mod gendex;
-mod components;
+use std::path::PathBuf;
 
-use crate::gendex::*;
-use crate::components::*;
+use ggez_test2::collision::Point;
+use ggez_test2::components::RenderLayer;
+use ggez_test2::render::*;
+use ggez_test2::replay::*;
+use ggez_test2::simulation::*;
 
 use ggez::*;
-use rand::prelude::*;
-use nalgebra as na;
-
-type Entity = GenerationalIndex;
-type EntityMap<T> = GenerationalIndexArray<T>;
-
-trait System {
-    fn process(ctx: &mut Context, state: &mut GameState) -> GameResult<()>;
-}
-
-struct CollissionSystem;
-impl System for CollissionSystem {
-    fn process(ctx: &mut Context, state: &mut GameState) -> GameResult<()> {
-        let screen_rect = graphics::screen_coordinates(ctx);
-        let mut rng = rand::thread_rng();
-
-        for e in state.entities.iter() {
-            if state.entity_allocator.is_live(*e) {
-                match (state.position_components.get_mut(*e), state.velocity_components.get_mut(*e), state.shape_components.get(*e)) {
-                    (Some(p), Some(v), Some(s)) => {
-                        let (w, h) = {
-                            match s.shape_type {
-                                ShapeType::Circle(r) => (r, r),
-                                ShapeType::Rectangle(w, h) => (w, h),
-                            }
-                        };
-                        // check if we hit the bottom of the screen
-                        if p.y + h >= screen_rect.h {
-                            v.yv *= -rng.gen::<f32>();
-                            v.xv *= 0.8;
-                        }
-                        // check if we hit the edge of the screen
-                        if p.x + w >= screen_rect.w || p.x <= 0.0 {
-                            v.xv *= -0.9;
-                        }
-
-                        // if both velocity components are 0, the entity is dead
-                        if v.xv >= -0.01 && v.xv <= 0.01 {
-                            state.entity_allocator.deallocate(*e);
-                        }
-                    },
-                    _ => (),
-                }
-            }
-        }
-
-        Ok(())
-    }
-}
-
-struct MovementSystem;
-impl System for MovementSystem {
-    fn process(ctx: &mut Context, state: &mut GameState) -> GameResult<()> {
-        let screen_rect = graphics::screen_coordinates(ctx);
-
-        for e in state.entities.iter() {
-            if state.entity_allocator.is_live(*e) {
-                match (state.position_components.get_mut(*e), state.velocity_components.get_mut(*e), state.shape_components.get(*e)) {
-                    (Some(p), Some(v), Some(s)) => {
-                        let (w, h) = {
-                            match s.shape_type {
-                                ShapeType::Circle(r) => (r, r),
-                                ShapeType::Rectangle(w, h) => (w, h),
-                            }
-                        };
-                        p.x = na::clamp(p.x + v.xv, screen_rect.left(), screen_rect.w - w);
-                        p.y = na::clamp(p.y + v.yv, screen_rect.top(), screen_rect.h - h);
-
-                        v.yv = na::clamp(v.yv + 0.15, -10.0, 10.0);
-                        if v.yv >= -0.01 && v.yv <= 0.01 {
-                            v.yv = 0.0;
-                        }
-
-                    },
-                    _ => (), // ignore if both position and velocity components are not avaialable
-                }
-            }
-        }
-
-        Ok(())
-    }
-}
-
-struct RenderSystem;
-impl System for RenderSystem {
-    fn process(ctx: &mut Context, state: &mut GameState) -> GameResult<()> {
-        let mut mb = graphics::MeshBuilder::new();  // use a mesh to optimise the render pipeline
-        let mut should_render_mesh = false;
-        for e in state.entities.iter() {
-            if state.entity_allocator.is_live(*e) {
-                should_render_mesh = true;
-                match (state.position_components.get(*e), state.shape_components.get(*e)) {
-                    (Some(p), Some(s)) => {
-                        match s.shape_type {
-                            ShapeType::Rectangle(w, h) => {
-                                mb.rectangle(
-                                    graphics::DrawMode::fill(), 
-                                    graphics::Rect::new(p.x, p.y, w, h), 
-                                    s.colour,
-                                );
-                            },
-                            ShapeType::Circle(r) => {
-                                mb.circle(graphics::DrawMode::fill(),
-                                    na::Point2::new(p.x, p.y), 
-                                    r, 1.0, s.colour);
-                            },
-                        }
-                    },
-                    _ => (),
-                }
-            }
-        }
-
-        if should_render_mesh == true {
-            let mesh = mb.build(ctx)?;
-            graphics::draw(ctx, &mesh, (na::Point2::new(0.0, 0.0),))?;
-        }
-
-        Ok(())
-    }
-}
 
 #[derive(Debug)]
 struct GameState {
-    pub entity_allocator: GenerationalIndexAllocator,
-    pub entities: Vec<Entity>,
-    pub position_components: EntityMap<Position>,
-    pub velocity_components: EntityMap<Velocity>,
-    pub shape_components: EntityMap<Shape>,
+    pub sim: Simulation,
+    /// where to save the recording when the window closes
+    pub record_path: Option<PathBuf>,
+    /// the world's images on the GPU
+    textures: GgezTextures,
 }
 
 impl GameState {
-    fn new() -> Self {
+    fn new(sim: Simulation, record_path: Option<PathBuf>) -> Self {
         GameState {
-            entity_allocator: GenerationalIndexAllocator::new(),
-            entities: Vec::new(),
-            position_components: EntityMap::new(),
-            velocity_components: EntityMap::new(),
-            shape_components: EntityMap::new(),
+            sim,
+            record_path,
+            textures: GgezTextures::default(),
         }
     }
 
-    fn draw_debug_info(&self, ctx: &mut Context) -> GameResult<()> {
-        let tf = graphics::TextFragment::new(format!("fps={:.0}, live_entities: {} / {}", 
-            timer::fps(ctx), 
-            self.entity_allocator.live_entity_count(),
-            self.entity_allocator.allocated_entity_count())
-        );
-        let text = graphics::Text::new(tf);
-
-        graphics::clear(ctx, graphics::Color::from_rgb(32, 32, 48));
-        graphics::draw(ctx, &text, (nalgebra::Point2::new(10.0, 10.0),))?;
-
-        Ok(())
-    }
-
-    fn generate_entities(&mut self) {
-        let mut rng = rand::thread_rng();
-        for _ in 0..1000 {
-            let e = self.entity_allocator.allocate();
-            self.entities.insert(e.index, e);
-            self.position_components.set(e, Position{ x: rng.gen::<f32>() * 1280.0, y: rng.gen::<f32>() * 900.0 });
-            self.velocity_components.set(e, Velocity{ xv: 1.0 + rng.gen::<f32>() * 15.0, yv: 0.0 });
-            self.shape_components.set(e, Shape{ 
-                shape_type: ShapeType::Circle(4.0), 
-                colour: graphics::Color::from_rgba(255, 255, 255, rng.gen::<u8>())
-            });
-        }
+    /// Not a method, the renderer holds on to the textures while it draws
+    fn draw_debug_info<R: Renderer>(sim: &Simulation, renderer: &mut R, fps: f64) -> GameResult<()> {
+        let mode = match (&sim.replay, &sim.desync) {
+            (Some(_), Some(desync)) => format!(", REPLAY DESYNC at tick {}", desync.tick),
+            (Some(_), None) => String::from(", REPLAY"),
+            (None, _) => String::new(),
+        };
+        let text = format!("fps={:.0}, live_entities: {} / {}{}", 
+            fps, 
+            sim.world.entity_allocator.live_entity_count(),
+            sim.world.entity_allocator.allocated_entity_count(),
+            mode);
+
+        renderer.draw_text(&text, Point::new(10.0, 10.0), graphics::WHITE)
     }
 }
 
 impl event::EventHandler for GameState {
     fn update(&mut self, ctx: &mut Context) -> GameResult<()> {
-        // run various systems
-
-        MovementSystem::process(ctx, self)?;
-        CollissionSystem::process(ctx, self)?;
+        // run as many fixed steps as fit into the time since the last frame
+        self.sim.advance(timer::delta(ctx));
 
         Ok(())
     }
 
     fn draw(&mut self, ctx: &mut Context) -> GameResult<()> {
-        graphics::clear(ctx, graphics::Color::from_rgb(20, 40, 80));
+        let fps = timer::fps(ctx);
+        let mut renderer = GgezRenderer::new(ctx, &mut self.textures);
+        renderer.clear(graphics::Color::from_rgb(32, 32, 48));
 
-        self.draw_debug_info(ctx)?;
+        RenderSystem::process(&mut renderer, &self.sim.world)?;
 
-        RenderSystem::process(ctx, self)?;
+        // the debug text goes over everything, and is hidden with the debug layer
+        if self.sim.world.render_layers.is_visible(RenderLayer::Debug) {
+            Self::draw_debug_info(&self.sim, &mut renderer, fps)?;
+        }
 
-        graphics::present(ctx)?;
-        Ok(())
+        renderer.present()
     }
 
     fn mouse_button_down_event(
         &mut self,
         _ctx: &mut Context,
-        _button: ggez::event::MouseButton,
-        _x: f32,
-        _y: f32,
+        button: ggez::event::MouseButton,
+        x: f32,
+        y: f32,
     ) {
-        self.generate_entities();
+        // inputs are recorded where they land in the world, not on the screen
+        let p = self.sim.world.screen_to_world(Point::new(x, y));
+        self.sim.queue_input(InputEvent::MouseDown { button, x: p.x, y: p.y });
+    }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: event::KeyCode,
+        _keymods: event::KeyMods,
+        _repeat: bool,
+    ) {
+        // only changes what is drawn, so it isn't a simulation input
+        if keycode == event::KeyCode::F1 {
+            self.sim.world.render_layers.toggle(RenderLayer::Debug);
+            return;
+        }
+        self.sim.queue_input(InputEvent::KeyDown { key: format!("{:?}", keycode) });
+    }
+
+    fn resize_event(&mut self, ctx: &mut Context, width: f32, height: f32) {
+        // keep drawing in window pixels, the simulation picks up the new size on its next step
+        if let Err(e) = graphics::set_screen_coordinates(ctx, graphics::Rect::new(0.0, 0.0, width, height)) {
+            eprintln!("ERROR: {}", e);
+        }
+        self.sim.queue_input(InputEvent::Resize { width, height });
     }
+
+    fn quit_event(&mut self, _ctx: &mut Context) -> bool {
+        if let (Some(path), Some(recording)) = (&self.record_path, &self.sim.recording) {
+            match recording.save(path) {
+                Ok(_) => println!("recording saved to {}", path.display()),
+                Err(e) => eprintln!("ERROR: could not save recording: {}", e),
+            }
+        }
+        false
+    }
+}
+
+struct Options {
+    seed: Option<u64>,
+    record: Option<PathBuf>,
+    replay: Option<PathBuf>,
+}
+
+/// Reads `[--seed S] [--record FILE] [--replay FILE]` from the command line
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options { seed: None, record: None, replay: None };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next().ok_or_else(|| format!("{} needs a value", arg))?;
+        match arg.as_str() {
+            "--seed" => options.seed = Some(value.parse().map_err(|_| format!("invalid seed: {}", value))?),
+            "--record" => options.record = Some(PathBuf::from(value)),
+            "--replay" => options.replay = Some(PathBuf::from(value)),
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+    Ok(options)
 }
 
 fn main() {
-    let state = &mut GameState::new();
+    let options = match parse_args() {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("ERROR: {}", e);
+            eprintln!("usage: ggez-test2 [--seed S] [--record FILE] [--replay FILE]");
+            return;
+        },
+    };
+    let recording = match &options.replay {
+        Some(path) => match Recording::load(path) {
+            Ok(recording) => Some(recording),
+            Err(e) => {
+                eprintln!("ERROR: could not load {}: {}", path.display(), e);
+                return;
+            },
+        },
+        None => None,
+    };
+
     let mut c = conf::Conf::new();
     c.window_mode.width = 1280.0;
     c.window_mode.height = 1024.0;
@@ -220,17 +164,31 @@ fn main() {
         .build()
         .expect("Failed to create ggez context!");
 
-    // setup the movable entity
-    state.generate_entities();
-
-    // setup the immovable entity
-    let e = state.entity_allocator.allocate();
-    state.entities.insert(e.index, e);
-    state.position_components.set(e, Position{ x: 640.0, y: 512.0 });
-    state.shape_components.set(e, Shape{ shape_type: ShapeType::Rectangle(20.0, 20.0), colour: graphics::Color::from_rgb(255, 128, 128)});
+    let sim = match recording {
+        Some(recording) => {
+            if let Err(e) = graphics::set_screen_coordinates(ctx, recording.screen) {
+                eprintln!("ERROR: {}", e);
+            }
+            println!("replaying seed: {}", recording.seed);
+            Simulation::from_recording(recording)
+        },
+        None => {
+            let seed = options.seed.unwrap_or_else(rand::random);
+            println!("seed: {}", seed);
+
+            let mut sim = Simulation::new(graphics::screen_coordinates(ctx), seed);
+            if options.record.is_some() {
+                sim.start_recording();
+            }
+            // setup the movable entities and the immovable one
+            sim.populate();
+            sim
+        },
+    };
+    let state = &mut GameState::new(sim, options.record);
         
     match event::run(ctx, event_loop, state) {
         Ok(_) => (),
-        Err(e) => println!("ERROR: {}", e),
+        Err(e) => eprintln!("ERROR: {}", e),
     }
 }
@@ -0,0 +1,458 @@
+use crate::collision::*;
+use crate::components::*;
+use crate::world::*;
+
+/// Overlap that is left alone by positional correction, so resting bodies don't jitter
+pub const PENETRATION_SLOP: f32 = 0.05;
+/// Share of the remaining overlap removed each step. Removing all of it at once overshoots.
+pub const CORRECTION_PERCENT: f32 = 0.8;
+
+/// How positions and velocities are advanced each step
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Integrator {
+    /// moves with the old velocity, then updates it. Simple but gains energy over time.
+    ExplicitEuler,
+    /// updates the velocity first and moves with the new one. Stable for the kind of bouncing
+    /// this simulation does, so it's the default.
+    SemiImplicitEuler,
+    /// velocity Verlet, exact for constant acceleration
+    Verlet,
+}
+
+/// World-wide physics settings. Units are pixels and seconds.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PhysicsSettings {
+    /// acceleration applied to every dynamic body
+    pub gravity: Vector,
+    pub integrator: Integrator,
+    /// share of its velocity a body loses per second, 0 for none
+    pub linear_damping: f32,
+    /// no body moves faster than this
+    pub max_speed: f32,
+    /// how often the contact and joint impulses are applied each step. A single pass fixes every
+    /// contact on its own, more passes let the support of a pile reach the bodies at its top and
+    /// a pull travel down a chain.
+    pub solver_iterations: u32,
+    /// bodies slower than this count as still
+    pub sleep_speed: f32,
+    /// seconds a body with a `Sleep` component has to be still before it falls asleep
+    pub time_to_sleep: f32,
+    /// despawns bodies that have slept this many seconds, `None` keeps them forever
+    pub despawn_after_sleeping: Option<f32>,
+}
+
+impl Default for PhysicsSettings {
+    fn default() -> Self {
+        PhysicsSettings {
+            gravity: Vector::new(0.0, 540.0),
+            integrator: Integrator::SemiImplicitEuler,
+            linear_damping: 0.0,
+            max_speed: 1000.0,
+            solver_iterations: 8,
+            sleep_speed: 10.0,
+            time_to_sleep: 0.5,
+            despawn_after_sleeping: None,
+        }
+    }
+}
+
+impl PhysicsSettings {
+    /// Applies damping and the speed limit to a velocity after a step of `dt`
+    pub fn limit_velocity(&self, velocity: Vector, dt: f32) -> Vector {
+        let velocity = velocity / (1.0 + dt * self.linear_damping);
+        let speed = velocity.norm();
+        if speed > self.max_speed {
+            velocity * (self.max_speed / speed)
+        } else {
+            velocity
+        }
+    }
+}
+
+/// Advances a body with constant `acceleration` by `dt`, returning the new position and velocity
+pub fn integrate(integrator: Integrator, position: Vector, velocity: Vector, acceleration: Vector, dt: f32) -> (Vector, Vector) {
+    match integrator {
+        Integrator::ExplicitEuler => (position + velocity * dt, velocity + acceleration * dt),
+        Integrator::SemiImplicitEuler => {
+            let velocity = velocity + acceleration * dt;
+            (position + velocity * dt, velocity)
+        },
+        Integrator::Verlet => (
+            position + velocity * dt + acceleration * (0.5 * dt * dt),
+            velocity + acceleration * dt,
+        ),
+    }
+}
+
+/// One side of a contact as the solver sees it
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Body {
+    pub velocity: Vector,
+    /// 0 for bodies that can't be moved
+    pub inverse_mass: f32,
+    pub material: PhysicsMaterial,
+}
+
+impl Body {
+    /// A body with infinite mass, e.g. an obstacle or the screen edge
+    pub fn fixed(material: PhysicsMaterial) -> Self {
+        Body {
+            velocity: Vector::new(0.0, 0.0),
+            inverse_mass: 0.0,
+            material,
+        }
+    }
+
+    /// Reads the entity's `RigidBody`, `Velocity` and `PhysicsMaterial`. Entities without a
+    /// rigid body and sleeping ones are treated as static, entities without a material use the
+    /// default one.
+    pub fn of(world: &World, e: Entity) -> Self {
+        let material = world.get::<PhysicsMaterial>(e).copied().unwrap_or_default();
+        if is_asleep(world, e) {
+            return Body::fixed(material);
+        }
+        match (world.get::<RigidBody>(e), world.get::<Velocity>(e)) {
+            // kinematic bodies keep their velocity, so they push what they run into, but with
+            // an inverse mass of 0 nothing pushes back
+            (Some(rb), Some(v)) if rb.is_moving() => Body {
+                velocity: Vector::new(v.xv, v.yv),
+                inverse_mass: rb.inverse_mass(),
+                material,
+            },
+            _ => Body::fixed(material),
+        }
+    }
+}
+
+pub fn is_asleep(world: &World, e: Entity) -> bool {
+    world.get::<Sleep>(e).map(|s| s.asleep).unwrap_or(false)
+}
+
+/// Whether the entity is a dynamic or kinematic body that isn't asleep
+pub fn is_awake(world: &World, e: Entity) -> bool {
+    world.get::<RigidBody>(e).map(|rb| rb.is_moving()).unwrap_or(false) && !is_asleep(world, e)
+}
+
+/// Changes the velocities of two touching bodies so they stop moving into each other, bouncing
+/// by the larger restitution of the two and losing sliding speed to friction. `normal` points
+/// from `a` to `b`.
+pub fn apply_impulse(a: &mut Body, b: &mut Body, normal: Vector) {
+    let inverse_mass_sum = a.inverse_mass + b.inverse_mass;
+    if inverse_mass_sum == 0.0 {
+        return;
+    }
+
+    let relative = b.velocity - a.velocity;
+    let normal_speed = relative.dot(&normal);
+    if normal_speed > 0.0 {
+        // already moving apart
+        return;
+    }
+
+    let restitution = a.material.restitution.max(b.material.restitution);
+    let j = -(1.0 + restitution) * normal_speed / inverse_mass_sum;
+    a.velocity -= normal * j * a.inverse_mass;
+    b.velocity += normal * j * b.inverse_mass;
+
+    let relative = b.velocity - a.velocity;
+    let tangent = relative - normal * relative.dot(&normal);
+    let sliding_speed = tangent.norm();
+    if sliding_speed > 0.0 {
+        let tangent = tangent / sliding_speed;
+        // Coulomb friction, never more than mu times the normal impulse and never enough to reverse the sliding
+        let friction = (a.material.friction * b.material.friction).sqrt();
+        let jt = (-sliding_speed / inverse_mass_sum).max(-j * friction);
+        a.velocity -= tangent * jt * a.inverse_mass;
+        b.velocity += tangent * jt * b.inverse_mass;
+    }
+}
+
+/// How far to move `a` and `b` to undo most of an overlap of `depth` along `normal`, split by
+/// inverse mass so fixed bodies stay put
+pub fn positional_correction(a: &Body, b: &Body, normal: Vector, depth: f32) -> (Vector, Vector) {
+    let inverse_mass_sum = a.inverse_mass + b.inverse_mass;
+    if inverse_mass_sum == 0.0 {
+        return (Vector::zeros(), Vector::zeros());
+    }
+
+    let correction = normal * ((depth - PENETRATION_SLOP).max(0.0) / inverse_mass_sum * CORRECTION_PERCENT);
+    (-correction * a.inverse_mass, correction * b.inverse_mass)
+}
+
+/// Applies the impulse for a contact between two entities, see `Manifold`
+pub fn contact_impulse(world: &mut World, manifold: &Manifold) {
+    let mut a = Body::of(world, manifold.a);
+    let mut b = Body::of(world, manifold.b);
+
+    apply_impulse(&mut a, &mut b, manifold.contact.normal);
+    write_back(world, manifold.a, &a, Vector::zeros());
+    write_back(world, manifold.b, &b, Vector::zeros());
+}
+
+/// Pushes two entities apart once the impulses are done
+pub fn correct_contact(world: &mut World, manifold: &Manifold) {
+    let a = Body::of(world, manifold.a);
+    let b = Body::of(world, manifold.b);
+
+    let (shift_a, shift_b) = positional_correction(&a, &b, manifold.contact.normal, manifold.contact.depth);
+    write_back(world, manifold.a, &a, shift_a);
+    write_back(world, manifold.b, &b, shift_b);
+}
+
+/// Applies the impulse for a contact between an entity and something fixed that isn't an
+/// entity, like the screen edge. `normal` points from the entity into the fixed side.
+pub fn fixed_contact_impulse(world: &mut World, e: Entity, normal: Vector, material: PhysicsMaterial) {
+    let mut body = Body::of(world, e);
+    apply_impulse(&mut body, &mut Body::fixed(material), normal);
+    write_back(world, e, &body, Vector::zeros());
+}
+
+/// Pushes an entity out of something fixed once the impulses are done
+pub fn correct_fixed_contact(world: &mut World, e: Entity, normal: Vector, depth: f32) {
+    let body = Body::of(world, e);
+    let (shift, _) = positional_correction(&body, &Body::fixed(body.material), normal, depth);
+    write_back(world, e, &body, shift);
+}
+
+/// Where a joint is attached to `e`, see `Joint::anchor_a`
+fn joint_anchor(world: &World, e: Entity, anchor: Vertex) -> Point {
+    let p = world.get::<Position>(e).copied().unwrap_or_default();
+    let angle = world.get::<Rotation>(e).map(|r| r.angle).unwrap_or(0.0);
+    let (sin, cos) = angle.sin_cos();
+    Point::new(p.x + anchor.x * cos - anchor.y * sin, p.y + anchor.x * sin + anchor.y * cos)
+}
+
+/// From the joint's anchor on `a` to its anchor on `b`
+fn joint_offset(world: &World, joint: &Joint) -> Vector {
+    joint_anchor(world, joint.b, joint.anchor_b) - joint_anchor(world, joint.a, joint.anchor_a)
+}
+
+/// Applies a spring's force for a step of `dt`. Springs are soft, so unlike the other joints
+/// they are applied once per step rather than on every solver iteration.
+pub fn spring_impulse(world: &mut World, joint: &Joint, dt: f32) {
+    let (rest_length, stiffness, damping) = match joint.kind {
+        JointKind::Spring { rest_length, stiffness, damping } => (rest_length, stiffness, damping),
+        _ => return,
+    };
+    let mut a = Body::of(world, joint.a);
+    let mut b = Body::of(world, joint.b);
+    let offset = joint_offset(world, joint);
+    let distance = offset.norm();
+    if distance == 0.0 {
+        return;
+    }
+
+    let normal = offset / distance;
+    let separating_speed = (b.velocity - a.velocity).dot(&normal);
+    // positive pulls the bodies together
+    let force = stiffness * (distance - rest_length) + damping * separating_speed;
+    let impulse = normal * force * dt;
+    a.velocity += impulse * a.inverse_mass;
+    b.velocity -= impulse * b.inverse_mass;
+    write_back(world, joint.a, &a, Vector::zeros());
+    write_back(world, joint.b, &b, Vector::zeros());
+}
+
+/// Changes the velocities of the bodies so they stop moving against a distance joint or a pin,
+/// applied with the contact impulses
+pub fn joint_impulse(world: &mut World, joint: &Joint) {
+    let mut a = Body::of(world, joint.a);
+    let mut b = Body::of(world, joint.b);
+    let inverse_mass_sum = a.inverse_mass + b.inverse_mass;
+    if inverse_mass_sum == 0.0 {
+        return;
+    }
+
+    let relative = b.velocity - a.velocity;
+    let impulse = match joint.kind {
+        JointKind::Distance { length, rope } => {
+            let offset = joint_offset(world, joint);
+            let distance = offset.norm();
+            // a slack rope doesn't pull
+            if distance == 0.0 || (rope && distance < length) {
+                return;
+            }
+            let normal = offset / distance;
+            let separating_speed = relative.dot(&normal);
+            // and a taut one doesn't push
+            if rope && separating_speed < 0.0 {
+                return;
+            }
+            normal * (-separating_speed / inverse_mass_sum)
+        },
+        JointKind::Pin => -relative / inverse_mass_sum,
+        JointKind::Spring { .. } => return,
+    };
+    a.velocity -= impulse * a.inverse_mass;
+    b.velocity += impulse * b.inverse_mass;
+    write_back(world, joint.a, &a, Vector::zeros());
+    write_back(world, joint.b, &b, Vector::zeros());
+}
+
+/// Moves the bodies of a distance joint or a pin back to where the joint holds them, once
+/// the impulses are done
+pub fn correct_joint(world: &mut World, joint: &Joint) {
+    let a = Body::of(world, joint.a);
+    let b = Body::of(world, joint.b);
+    let inverse_mass_sum = a.inverse_mass + b.inverse_mass;
+    if inverse_mass_sum == 0.0 {
+        return;
+    }
+
+    let offset = joint_offset(world, joint);
+    let error = match joint.kind {
+        JointKind::Distance { length, rope } => {
+            let distance = offset.norm();
+            if distance == 0.0 || (rope && distance <= length) {
+                return;
+            }
+            offset * ((distance - length) / distance)
+        },
+        JointKind::Pin => offset,
+        JointKind::Spring { .. } => return,
+    };
+    let correction = error * (CORRECTION_PERCENT / inverse_mass_sum);
+    write_back(world, joint.a, &a, correction * a.inverse_mass);
+    write_back(world, joint.b, &b, -correction * b.inverse_mass);
+}
+
+fn write_back(world: &mut World, e: Entity, body: &Body, shift: Vector) {
+    if body.inverse_mass == 0.0 {
+        return;
+    }
+    if let Some(v) = world.get_mut::<Velocity>(e) {
+        v.xv = body.velocity.x;
+        v.yv = body.velocity.y;
+    }
+    if let Some(p) = world.get_mut::<Position>(e) {
+        p.x += shift.x;
+        p.y += shift.y;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reflect::Reflect;
+
+    fn material(restitution: f32, friction: f32) -> PhysicsMaterial {
+        PhysicsMaterial { restitution, friction, density: 1.0 }
+    }
+
+    fn body(vx: f32, vy: f32, inverse_mass: f32, material: PhysicsMaterial) -> Body {
+        Body { velocity: Vector::new(vx, vy), inverse_mass, material }
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{} != {}", a, b);
+    }
+
+    fn v(x: f32, y: f32) -> Vector {
+        Vector::new(x, y)
+    }
+
+    #[test]
+    fn integrators() {
+        let (p, vel, a, dt) = (v(0.0, 0.0), v(1.0, 0.0), v(0.0, 2.0), 0.5);
+
+        assert_eq!(integrate(Integrator::ExplicitEuler, p, vel, a, dt), (v(0.5, 0.0), v(1.0, 1.0)));
+        assert_eq!(integrate(Integrator::SemiImplicitEuler, p, vel, a, dt), (v(0.5, 0.5), v(1.0, 1.0)));
+        assert_eq!(integrate(Integrator::Verlet, p, vel, a, dt), (v(0.5, 0.25), v(1.0, 1.0)));
+    }
+
+    #[test]
+    fn verlet_is_exact_under_gravity() {
+        // falling for one second in 64 steps ends where s = a * t^2 / 2 says it should
+        let (mut p, mut vel, a) = (v(0.0, 0.0), v(0.0, 0.0), v(0.0, 10.0));
+        for _ in 0..64 {
+            let next = integrate(Integrator::Verlet, p, vel, a, 1.0 / 64.0);
+            p = next.0;
+            vel = next.1;
+        }
+
+        assert_close(p.y, 5.0);
+        assert_close(vel.y, 10.0);
+    }
+
+    #[test]
+    fn damping_and_speed_limit() {
+        let settings = PhysicsSettings { linear_damping: 1.0, max_speed: 100.0, ..PhysicsSettings::default() };
+
+        assert_eq!(settings.limit_velocity(v(10.0, 0.0), 1.0), v(5.0, 0.0));
+        assert_close(settings.limit_velocity(v(300.0, 400.0), 0.0).norm(), 100.0);
+    }
+
+    #[test]
+    fn equal_masses_swap_velocities_when_elastic() {
+        let mut a = body(1.0, 0.0, 1.0, material(1.0, 0.0));
+        let mut b = body(-1.0, 0.0, 1.0, material(1.0, 0.0));
+        apply_impulse(&mut a, &mut b, Vector::new(1.0, 0.0));
+
+        assert_close(a.velocity.x, -1.0);
+        assert_close(b.velocity.x, 1.0);
+    }
+
+    #[test]
+    fn restitution_scales_the_bounce_off_fixed_bodies() {
+        let mut ball = body(0.0, 4.0, 0.5, material(0.25, 0.0));
+        let mut floor = Body::fixed(material(0.0, 0.0));
+        apply_impulse(&mut ball, &mut floor, Vector::new(0.0, 1.0));
+
+        assert_close(ball.velocity.y, -1.0);
+        assert_eq!(floor.velocity, Vector::zeros());
+    }
+
+    #[test]
+    fn friction_slows_sliding_without_reversing_it() {
+        // the ball lands while sliding, so the floor takes away some of its sideways speed
+        let mut ball = body(3.0, 1.0, 1.0, material(0.0, 0.5));
+        let mut floor = Body::fixed(material(0.0, 0.5));
+        apply_impulse(&mut ball, &mut floor, Vector::new(0.0, 1.0));
+        assert_close(ball.velocity.y, 0.0);
+        assert_close(ball.velocity.x, 2.5);
+
+        // with enough friction the sliding stops but doesn't reverse
+        let mut ball = body(0.1, 1.0, 1.0, material(0.0, 1.0));
+        apply_impulse(&mut ball, &mut Body::fixed(material(0.0, 1.0)), Vector::new(0.0, 1.0));
+        assert_close(ball.velocity.x, 0.0);
+    }
+
+    #[test]
+    fn separating_bodies_are_left_alone() {
+        let mut a = body(-1.0, 0.0, 1.0, PhysicsMaterial::default());
+        let mut b = body(1.0, 0.0, 1.0, PhysicsMaterial::default());
+        apply_impulse(&mut a, &mut b, Vector::new(1.0, 0.0));
+
+        assert_eq!(a.velocity, Vector::new(-1.0, 0.0));
+        assert_eq!(b.velocity, Vector::new(1.0, 0.0));
+    }
+
+    #[test]
+    fn correction_is_split_by_inverse_mass() {
+        let light = body(0.0, 0.0, 3.0, PhysicsMaterial::default());
+        let heavy = body(0.0, 0.0, 1.0, PhysicsMaterial::default());
+        let (a, b) = positional_correction(&light, &heavy, Vector::new(1.0, 0.0), 1.0 + PENETRATION_SLOP);
+
+        assert_close(a.x, -0.75 * CORRECTION_PERCENT);
+        assert_close(b.x, 0.25 * CORRECTION_PERCENT);
+
+        let (a, _) = positional_correction(&light, &Body::fixed(PhysicsMaterial::default()), Vector::new(1.0, 0.0), PENETRATION_SLOP);
+        assert_eq!(a, Vector::zeros());
+    }
+
+    #[test]
+    fn massless_bodies_keep_a_finite_inverse_mass() {
+        for mass in &[0.0, -1.0, f32::NAN] {
+            let rb = RigidBody::dynamic(*mass);
+            assert_eq!(rb.mass(), MIN_MASS);
+            assert!(rb.inverse_mass().is_finite());
+        }
+        assert!(RigidBody::dynamic_from_shape(&ShapeType::Circle(0.0), &PhysicsMaterial::default()).inverse_mass().is_finite());
+
+        // editing the mass through reflection can't get it out of sync with the inverse mass
+        let mut rb = RigidBody::dynamic(2.0);
+        (&mut rb as &mut dyn Reflect).set_path("mass", 0.0f32).unwrap();
+        assert_eq!(rb.inverse_mass(), 1.0 / MIN_MASS);
+        assert_eq!(RigidBody::fixed().inverse_mass(), 0.0);
+    }
+}
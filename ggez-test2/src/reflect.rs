@@ -0,0 +1,340 @@
+use std::any::Any;
+use std::fmt;
+use std::str::FromStr;
+
+use ggez::graphics::{Color, Rect};
+
+pub use reflect_derive::Reflect;
+
+/// Name and type of a reflected field, as listed by `Reflect::fields`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FieldInfo {
+    pub name: &'static str,
+    pub type_name: &'static str,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReflectError {
+    /// a path segment did not name a field, or named an enum variant that is not active
+    NoSuchField(String),
+    TypeMismatch { expected: &'static str, found: &'static str },
+    /// the value is a struct or enum and can not be parsed from a string
+    NotAValue(&'static str),
+    ParseError(String),
+}
+
+impl fmt::Display for ReflectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReflectError::NoSuchField(path) => write!(f, "no such field: {}", path),
+            ReflectError::TypeMismatch { expected, found } => write!(f, "type mismatch: expected {}, found {}", expected, found),
+            ReflectError::NotAValue(type_name) => write!(f, "{} can not be set from a string", type_name),
+            ReflectError::ParseError(s) => write!(f, "could not parse {:?}", s),
+        }
+    }
+}
+
+impl std::error::Error for ReflectError {}
+
+/// Generic access to the fields of a component, used by inspectors and editors.
+/// Usually implemented with `#[derive(Reflect)]`.
+///
+/// Fields are addressed by name, tuple fields by index. For enums the name of the
+/// active variant resolves to the enum itself, so a circle's radius is found at
+/// `shape_type.Circle.0` on a `Shape`.
+pub trait Reflect: Any {
+    fn type_name(&self) -> &'static str;
+
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    /// Fields of a struct, or of the active variant of an enum
+    fn fields(&self) -> Vec<FieldInfo> {
+        Vec::new()
+    }
+
+    /// Calls `visit` for each field in the order of `fields`, without allocating the list
+    fn visit_fields(&self, visit: &mut dyn FnMut(&'static str, &dyn Reflect)) {
+        for info in self.fields() {
+            if let Some(field) = self.field(info.name) {
+                visit(info.name, field);
+            }
+        }
+    }
+
+    fn field(&self, _name: &str) -> Option<&dyn Reflect> {
+        None
+    }
+
+    fn field_mut(&mut self, _name: &str) -> Option<&mut dyn Reflect> {
+        None
+    }
+
+    /// Name of the active variant, `None` if this is not an enum
+    fn variant(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Overwrites a plain value (number, bool, string) by parsing `s`
+    fn set_from_str(&mut self, _s: &str) -> Result<(), ReflectError> {
+        Err(ReflectError::NotAValue(self.type_name()))
+    }
+
+    /// Resolves a dot separated path such as `shape_type.Circle.0`
+    fn path(&self, path: &str) -> Result<&dyn Reflect, ReflectError> {
+        let (head, rest) = split_path(path);
+        let field = self.field(head).ok_or_else(|| ReflectError::NoSuchField(path.to_string()))?;
+        match rest {
+            Some(rest) => field.path(rest),
+            None => Ok(field),
+        }
+    }
+
+    fn path_mut(&mut self, path: &str) -> Result<&mut dyn Reflect, ReflectError> {
+        let (head, rest) = split_path(path);
+        let field = self.field_mut(head).ok_or_else(|| ReflectError::NoSuchField(path.to_string()))?;
+        match rest {
+            Some(rest) => field.path_mut(rest),
+            None => Ok(field),
+        }
+    }
+}
+
+fn split_path(path: &str) -> (&str, Option<&str>) {
+    match path.find('.') {
+        Some(i) => (&path[..i], Some(&path[i + 1..])),
+        None => (path, None),
+    }
+}
+
+impl dyn Reflect {
+    pub fn downcast_ref<T: Reflect>(&self) -> Option<&T> {
+        self.as_any().downcast_ref::<T>()
+    }
+
+    pub fn downcast_mut<T: Reflect>(&mut self) -> Option<&mut T> {
+        self.as_any_mut().downcast_mut::<T>()
+    }
+
+    /// Reads the value at `path`, which must be of type `T`
+    pub fn get_path<T: Reflect + Clone>(&self, path: &str) -> Result<T, ReflectError> {
+        let field = self.path(path)?;
+        field.downcast_ref::<T>().cloned().ok_or(ReflectError::TypeMismatch {
+            expected: std::any::type_name::<T>(),
+            found: field.type_name(),
+        })
+    }
+
+    /// Overwrites the value at `path`, which must be of type `T`
+    pub fn set_path<T: Reflect>(&mut self, path: &str, value: T) -> Result<(), ReflectError> {
+        let field = self.path_mut(path)?;
+        let found = field.type_name();
+        match field.downcast_mut::<T>() {
+            Some(f) => {
+                *f = value;
+                Ok(())
+            },
+            None => Err(ReflectError::TypeMismatch { expected: std::any::type_name::<T>(), found }),
+        }
+    }
+}
+
+macro_rules! impl_reflect_value {
+    ($($ty:ty),*) => {
+        $(
+            impl Reflect for $ty {
+                fn type_name(&self) -> &'static str {
+                    stringify!($ty)
+                }
+
+                fn as_any(&self) -> &dyn Any {
+                    self
+                }
+
+                fn as_any_mut(&mut self) -> &mut dyn Any {
+                    self
+                }
+
+                fn set_from_str(&mut self, s: &str) -> Result<(), ReflectError> {
+                    *self = <$ty>::from_str(s.trim()).map_err(|_| ReflectError::ParseError(s.to_string()))?;
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+impl_reflect_value!(f32, f64, i32, i64, u8, u16, u32, u64, usize, bool, String);
+
+// ggez's colour is foreign so it can't use the derive
+impl Reflect for Color {
+    fn type_name(&self) -> &'static str {
+        "Color"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn fields(&self) -> Vec<FieldInfo> {
+        ["r", "g", "b", "a"].iter().map(|name| FieldInfo { name, type_name: "f32" }).collect()
+    }
+
+    fn visit_fields(&self, visit: &mut dyn FnMut(&'static str, &dyn Reflect)) {
+        visit("r", &self.r);
+        visit("g", &self.g);
+        visit("b", &self.b);
+        visit("a", &self.a);
+    }
+
+    fn field(&self, name: &str) -> Option<&dyn Reflect> {
+        match name {
+            "r" => Some(&self.r),
+            "g" => Some(&self.g),
+            "b" => Some(&self.b),
+            "a" => Some(&self.a),
+            _ => None,
+        }
+    }
+
+    fn field_mut(&mut self, name: &str) -> Option<&mut dyn Reflect> {
+        match name {
+            "r" => Some(&mut self.r),
+            "g" => Some(&mut self.g),
+            "b" => Some(&mut self.b),
+            "a" => Some(&mut self.a),
+            _ => None,
+        }
+    }
+}
+
+// ggez's rect is foreign too
+impl Reflect for Rect {
+    fn type_name(&self) -> &'static str {
+        "Rect"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn fields(&self) -> Vec<FieldInfo> {
+        ["x", "y", "w", "h"].iter().map(|name| FieldInfo { name, type_name: "f32" }).collect()
+    }
+
+    fn visit_fields(&self, visit: &mut dyn FnMut(&'static str, &dyn Reflect)) {
+        visit("x", &self.x);
+        visit("y", &self.y);
+        visit("w", &self.w);
+        visit("h", &self.h);
+    }
+
+    fn field(&self, name: &str) -> Option<&dyn Reflect> {
+        match name {
+            "x" => Some(&self.x),
+            "y" => Some(&self.y),
+            "w" => Some(&self.w),
+            "h" => Some(&self.h),
+            _ => None,
+        }
+    }
+
+    fn field_mut(&mut self, name: &str) -> Option<&mut dyn Reflect> {
+        match name {
+            "x" => Some(&mut self.x),
+            "y" => Some(&mut self.y),
+            "w" => Some(&mut self.w),
+            "h" => Some(&mut self.h),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::*;
+
+    fn circle() -> Shape {
+        Shape { shape_type: ShapeType::Circle(4.0), colour: ggez::graphics::WHITE }
+    }
+
+    #[test]
+    fn struct_fields() {
+        let p = Position { x: 1.0, y: 2.0 };
+        assert_eq!(p.fields(), vec![
+            FieldInfo { name: "x", type_name: "f32" },
+            FieldInfo { name: "y", type_name: "f32" },
+        ]);
+        assert_eq!((&p as &dyn Reflect).get_path::<f32>("y"), Ok(2.0));
+    }
+
+    #[test]
+    fn visit_fields_matches_fields() {
+        let values: Vec<Box<dyn Reflect>> = vec![
+            Box::new(Position { x: 1.0, y: 2.0 }),
+            Box::new(ShapeType::Rectangle(3.0, 4.0)),
+            Box::new(ConvexPolygon::regular(5, 1.0).unwrap()),
+            Box::new(ggez::graphics::WHITE),
+            Box::new(Rect::new(1.0, 2.0, 3.0, 4.0)),
+            Box::new(Particle),
+        ];
+        for value in &values {
+            let mut visited = Vec::new();
+            value.visit_fields(&mut |name, field| visited.push((name, field.type_name())));
+            let listed: Vec<_> = value.fields().iter().map(|f| (f.name, f.type_name)).collect();
+            assert_eq!(visited, listed);
+        }
+    }
+
+    #[test]
+    fn enum_variant_paths() {
+        let mut s = circle();
+        let r: &mut dyn Reflect = &mut s;
+
+        assert_eq!(r.get_path::<f32>("shape_type.Circle.0"), Ok(4.0));
+        assert_eq!(r.path("shape_type").unwrap().variant(), Some("Circle"));
+        assert_eq!(r.path("shape_type").unwrap().fields(), vec![FieldInfo { name: "0", type_name: "f32" }]);
+        assert!(r.path("shape_type.Rectangle.0").is_err());
+
+        r.set_path("shape_type.Circle.0", 8.0f32).unwrap();
+        r.path_mut("colour.a").unwrap().set_from_str("0.5").unwrap();
+        assert_eq!(s.shape_type, ShapeType::Circle(8.0));
+        assert_eq!(s.colour.a, 0.5);
+    }
+
+    #[test]
+    fn polygon_corners_by_index() {
+        let polygon = ConvexPolygon::new(&[(0.0, 0.0), (4.0, 0.0), (0.0, 3.0)]).unwrap();
+        let mut s = Shape { shape_type: ShapeType::Polygon(polygon), colour: ggez::graphics::WHITE };
+        let r: &mut dyn Reflect = &mut s;
+
+        assert_eq!(r.path("shape_type.Polygon.0").unwrap().fields().len(), 3);
+        assert_eq!(r.get_path::<f32>("shape_type.Polygon.0.1.x"), Ok(4.0));
+        assert!(r.path("shape_type.Polygon.0.3").is_err());
+        r.set_path("shape_type.Polygon.0.2.y", 6.0f32).unwrap();
+        assert_eq!(s.shape_type.area(), 12.0);
+    }
+
+    #[test]
+    fn errors() {
+        let mut s = circle();
+        let r: &mut dyn Reflect = &mut s;
+
+        assert_eq!(r.path("nope").err(), Some(ReflectError::NoSuchField("nope".to_string())));
+        assert!(matches!(r.set_path("colour.r", 1u8), Err(ReflectError::TypeMismatch { .. })));
+        assert_eq!(r.path_mut("colour").unwrap().set_from_str("1"), Err(ReflectError::NotAValue("Color")));
+        assert_eq!(
+            r.path_mut("colour.r").unwrap().set_from_str("red"),
+            Err(ReflectError::ParseError("red".to_string()))
+        );
+    }
+}
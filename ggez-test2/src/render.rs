@@ -0,0 +1,476 @@
+use std::collections::hash_map::{Entry, HashMap};
+
+use ggez::graphics::spritebatch::SpriteBatch;
+use ggez::graphics::{self, Color, DrawMode, DrawParam, MeshBuilder, Rect};
+use ggez::{Context, GameResult};
+
+use crate::collision::*;
+use crate::components::*;
+use crate::images::*;
+use crate::world::*;
+
+/// Maps world coordinates to screen pixels: scales by `zoom`, turns clockwise by `rotation`
+/// radians and then moves by `translation`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct View {
+    pub translation: Vector,
+    pub rotation: f32,
+    pub zoom: f32,
+}
+
+impl View {
+    pub fn to_screen(&self, point: Point) -> Point {
+        let (sin, cos) = self.rotation.sin_cos();
+        let p = point.coords * self.zoom;
+        Point::new(p.x * cos - p.y * sin, p.x * sin + p.y * cos) + self.translation
+    }
+
+    pub fn to_world(&self, point: Point) -> Point {
+        let (sin, cos) = self.rotation.sin_cos();
+        let p = point - self.translation;
+        Point::new(p.x * cos + p.y * sin, -p.x * sin + p.y * cos) / self.zoom
+    }
+}
+
+/// World coordinates are screen pixels
+impl Default for View {
+    fn default() -> Self {
+        View { translation: Vector::zeros(), rotation: 0.0, zoom: 1.0 }
+    }
+}
+
+/// A `Sprite` placed in the world, ready to be drawn
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SpriteQuad {
+    pub image: ImageHandle,
+    /// see `Sprite::source`
+    pub source: Rect,
+    /// where the pivot ends up
+    pub position: Point,
+    /// radians, around the pivot
+    pub rotation: f32,
+    /// in world units
+    pub size: Vector,
+    /// the point of the quad at `position`, as a fraction of its size from the top left as it
+    /// is drawn, so after flipping
+    pub pivot: Vector,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    pub tint: Color,
+}
+
+impl Sprite {
+    /// The sprite with its `anchor` at `position`, turned by `angle`, the same as a rectangle
+    /// as big as it would be. `None` if its image isn't in `images`.
+    pub fn quad(&self, position: Point, angle: f32, anchor: Anchor, images: &Images) -> Option<SpriteQuad> {
+        let image = images.get(self.image)?;
+        let size = Vector::new(self.source.w * image.width as f32, self.source.h * image.height as f32) * self.scale;
+        let pivot = match anchor {
+            Anchor::Origin => Vector::zeros(),
+            Anchor::Bounds(x, y) => Vector::new(x, y),
+        };
+        Some(SpriteQuad {
+            image: self.image,
+            source: self.source,
+            position,
+            rotation: angle,
+            size,
+            pivot,
+            flip_x: self.flip_x,
+            flip_y: self.flip_y,
+            tint: self.tint,
+        })
+    }
+}
+
+/// Something the world can be drawn with. Shapes and sprites are drawn in world coordinates
+/// through the current `View`, text is drawn in screen pixels. Nothing is guaranteed to show up
+/// before `present`.
+pub trait Renderer {
+    fn clear(&mut self, colour: Color);
+
+    /// The view used by the shapes drawn after it
+    fn set_camera(&mut self, view: View) -> GameResult<()>;
+
+    fn draw_shape(&mut self, collider: &Collider, colour: Color) -> GameResult<()>;
+
+    /// `image` is what `quad.image` refers to
+    fn draw_sprite(&mut self, quad: &SpriteQuad, image: &ImageData) -> GameResult<()>;
+
+    /// `position` is the top left corner of the text
+    fn draw_text(&mut self, text: &str, position: Point, colour: Color) -> GameResult<()>;
+
+    fn present(&mut self) -> GameResult<()>;
+}
+
+/// Which `RenderLayer`s are drawn, all of them to begin with
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct RenderLayers {
+    /// one bit per layer, in the order of `RenderLayer::ALL`
+    hidden: u8,
+}
+
+impl RenderLayers {
+    pub fn is_visible(&self, layer: RenderLayer) -> bool {
+        self.hidden & (1 << layer as u8) == 0
+    }
+
+    pub fn set_visible(&mut self, layer: RenderLayer, visible: bool) {
+        if visible {
+            self.hidden &= !(1 << layer as u8);
+        } else {
+            self.hidden |= 1 << layer as u8;
+        }
+    }
+
+    pub fn toggle(&mut self, layer: RenderLayer) {
+        self.hidden ^= 1 << layer as u8;
+    }
+}
+
+/// Draws every entity with a `Position` and a `Shape` or a `Sprite` in visible layers, sorted
+/// by `RenderLayer` and then `ZIndex`. An entity with both is drawn as its sprite. Everything
+/// but the `Ui` layer is seen through the world's camera.
+pub struct RenderSystem;
+impl RenderSystem {
+    pub fn process<R: Renderer>(renderer: &mut R, world: &World) -> GameResult<()> {
+        let alpha = world.time.alpha;
+        let mut drawn = Vec::new();
+        for e in world.entities.iter() {
+            if !world.is_live(*e) || !world.has::<Position>(*e) || !(world.has::<Shape>(*e) || world.has::<Sprite>(*e)) {
+                continue;
+            }
+            let layer = world.get::<RenderLayer>(*e).copied().unwrap_or_default();
+            if world.render_layers.is_visible(layer) {
+                let z = world.get::<ZIndex>(*e).copied().unwrap_or_default();
+                drawn.push((layer, z, world.get::<Sprite>(*e).map(|s| s.image), *e));
+            }
+        }
+        // stable, so ties stay in entity order. Shapes come before sprites and sprites are
+        // grouped by image so a renderer can draw each group in one go.
+        drawn.sort_by_key(|(layer, z, image, _)| (*layer, *z, *image));
+
+        let camera = world.camera.view(world.screen, alpha);
+        for (layer, _, _, e) in drawn {
+            renderer.set_camera(if layer == RenderLayer::Ui { View::default() } else { camera })?;
+            let p = world.get::<Position>(e).unwrap();
+            // draw between the last two simulation steps so movement stays smooth
+            // when the frame rate and the simulation rate differ
+            let p = match world.get::<PreviousPosition>(e) {
+                Some(prev) => Position{ x: prev.x + (p.x - prev.x) * alpha, y: prev.y + (p.y - prev.y) * alpha },
+                None => *p,
+            };
+            let (angle, anchor) = world.placement(e);
+            if let Some(sprite) = world.get::<Sprite>(e) {
+                // a sprite whose image was never added has nothing to show
+                if let Some(quad) = sprite.quad(Point::new(p.x, p.y), angle, anchor, &world.images) {
+                    renderer.draw_sprite(&quad, world.images.get(quad.image).unwrap())?;
+                }
+            } else {
+                let s = world.get::<Shape>(e).unwrap();
+                renderer.draw_shape(&s.collider(&p, angle, anchor), s.colour)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The images of the world's `Images` uploaded to the GPU, each the first time it is drawn. Kept
+/// from frame to frame by whoever owns the `Context`.
+#[derive(Debug, Default)]
+pub struct GgezTextures {
+    textures: HashMap<ImageHandle, graphics::Image>,
+}
+
+impl GgezTextures {
+    fn get(&mut self, ctx: &mut Context, handle: ImageHandle, image: &ImageData) -> GameResult<&graphics::Image> {
+        if let Entry::Vacant(entry) = self.textures.entry(handle) {
+            entry.insert(graphics::Image::from_rgba8(ctx, image.width as u16, image.height as u16, &image.pixels)?);
+        }
+        Ok(&self.textures[&handle])
+    }
+}
+
+/// What `GgezRenderer` has collected but not drawn yet
+enum Batch {
+    Empty,
+    Shapes(MeshBuilder),
+    Sprites(ImageHandle, SpriteBatch),
+}
+
+/// Draws through ggez. Shapes are collected into one mesh and sprites into one sprite batch per
+/// image. A batch is drawn whenever something that can't go into it comes along, the view
+/// changes or the frame is presented.
+pub struct GgezRenderer<'a> {
+    ctx: &'a mut Context,
+    textures: &'a mut GgezTextures,
+    batch: Batch,
+    view: View,
+}
+
+impl<'a> GgezRenderer<'a> {
+    pub fn new(ctx: &'a mut Context, textures: &'a mut GgezTextures) -> Self {
+        GgezRenderer { ctx, textures, batch: Batch::Empty, view: View::default() }
+    }
+
+    fn flush(&mut self) -> GameResult<()> {
+        let view = self.view;
+        let param = DrawParam::new()
+            .dest(Point::from(view.translation))
+            .rotation(view.rotation)
+            .scale(Vector::new(view.zoom, view.zoom));
+        match std::mem::replace(&mut self.batch, Batch::Empty) {
+            // ggez refuses to build a mesh without anything in it, so there's no empty one
+            Batch::Empty => Ok(()),
+            Batch::Shapes(mesh) => {
+                let mesh = mesh.build(self.ctx)?;
+                graphics::draw(self.ctx, &mesh, param)
+            },
+            Batch::Sprites(_, sprites) => graphics::draw(self.ctx, &sprites, param),
+        }
+    }
+}
+
+impl<'a> Renderer for GgezRenderer<'a> {
+    fn clear(&mut self, colour: Color) {
+        self.batch = Batch::Empty;
+        graphics::clear(self.ctx, colour);
+    }
+
+    fn set_camera(&mut self, view: View) -> GameResult<()> {
+        if view != self.view {
+            // whatever was collected already was meant for the old view
+            self.flush()?;
+            self.view = view;
+        }
+        Ok(())
+    }
+
+    fn draw_shape(&mut self, collider: &Collider, colour: Color) -> GameResult<()> {
+        if !matches!(self.batch, Batch::Shapes(_)) {
+            self.flush()?;
+            self.batch = Batch::Shapes(MeshBuilder::new());
+        }
+        let mb = match &mut self.batch {
+            Batch::Shapes(mb) => mb,
+            _ => unreachable!(),
+        };
+        match *collider {
+            Collider::Aabb { min, max } => {
+                mb.rectangle(DrawMode::fill(), graphics::Rect::new(min.x, min.y, max.x - min.x, max.y - min.y), colour);
+            },
+            Collider::Circle { center, radius } => {
+                mb.circle(DrawMode::fill(), center, radius, 1.0, colour);
+            },
+            Collider::Polygon { vertices, count } => {
+                mb.polygon(DrawMode::fill(), &vertices[..count], colour)?;
+            },
+            Collider::Capsule { a, b, radius: 0.0 } => {
+                mb.line(&[a, b], 1.0, colour)?;
+            },
+            Collider::Capsule { a, b, radius } => {
+                // a line as wide as the capsule with a round cap on each end
+                if a != b {
+                    mb.line(&[a, b], radius * 2.0, colour)?;
+                }
+                mb.circle(DrawMode::fill(), a, radius, 1.0, colour);
+                mb.circle(DrawMode::fill(), b, radius, 1.0, colour);
+            },
+        }
+        Ok(())
+    }
+
+    fn draw_sprite(&mut self, quad: &SpriteQuad, image: &ImageData) -> GameResult<()> {
+        if !matches!(self.batch, Batch::Sprites(handle, _) if handle == quad.image) {
+            self.flush()?;
+            let texture = self.textures.get(self.ctx, quad.image, image)?.clone();
+            self.batch = Batch::Sprites(quad.image, SpriteBatch::new(texture));
+        }
+        let sprites = match &mut self.batch {
+            Batch::Sprites(_, sprites) => sprites,
+            _ => unreachable!(),
+        };
+        // ggez scales by the size of the source rect in pixels, and a negative scale flips the
+        // image around its offset, which is a point of the image rather than of the quad
+        let pixels = Vector::new(quad.source.w * image.width as f32, quad.source.h * image.height as f32);
+        let scale = Vector::new(
+            if quad.flip_x { -quad.size.x } else { quad.size.x } / pixels.x,
+            if quad.flip_y { -quad.size.y } else { quad.size.y } / pixels.y,
+        );
+        let offset = Point::new(
+            if quad.flip_x { 1.0 - quad.pivot.x } else { quad.pivot.x },
+            if quad.flip_y { 1.0 - quad.pivot.y } else { quad.pivot.y },
+        );
+        sprites.add(DrawParam::new()
+            .src(quad.source)
+            .dest(quad.position)
+            .rotation(quad.rotation)
+            .scale(scale)
+            .offset(offset)
+            .color(quad.tint));
+        Ok(())
+    }
+
+    fn draw_text(&mut self, text: &str, position: Point, colour: Color) -> GameResult<()> {
+        self.flush()?;
+        let text = graphics::Text::new(graphics::TextFragment::new(text));
+        graphics::draw(self.ctx, &text, (position, colour))
+    }
+
+    fn present(&mut self) -> GameResult<()> {
+        self.flush()?;
+        graphics::present(self.ctx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::software::SoftwareRenderer;
+    use ggez::graphics::{BLACK, WHITE};
+
+    fn assert_close(a: Point, b: Point) {
+        assert!((a - b).norm() < 1e-4, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn views_go_both_ways() {
+        let view = View { translation: Vector::new(100.0, 50.0), rotation: std::f32::consts::FRAC_PI_2, zoom: 2.0 };
+        // turning clockwise on screen takes +x to +y
+        assert_close(view.to_screen(Point::new(10.0, 0.0)), Point::new(100.0, 70.0));
+        assert_close(view.to_world(Point::new(100.0, 70.0)), Point::new(10.0, 0.0));
+        let p = Point::new(-3.0, 7.5);
+        assert_close(view.to_world(view.to_screen(p)), p);
+        assert_eq!(View::default().to_screen(p), p);
+    }
+
+    #[test]
+    fn renders_the_world_headless() {
+        let mut world = World::new();
+        world.screen = Rect::new(0.0, 0.0, 40.0, 30.0);
+        let red = Color::from_rgb(255, 0, 0);
+        let blue = Color::from_rgb(0, 0, 255);
+        let block = world.spawn();
+        world.set(block, Position { x: 2.0, y: 2.0 });
+        world.set(block, Shape { shape_type: ShapeType::Rectangle(10.0, 5.0), colour: red });
+        let ball = world.spawn();
+        world.set(ball, Position { x: 30.0, y: 20.0 });
+        world.set(ball, Shape { shape_type: ShapeType::Circle(5.0), colour: blue });
+        // halfway between the last two steps
+        world.set(ball, PreviousPosition { x: 20.0, y: 20.0 });
+        world.time.alpha = 0.5;
+        let hidden = world.spawn();
+        world.set(hidden, Position { x: 0.0, y: 0.0 });
+
+        let mut renderer = SoftwareRenderer::new(40, 30);
+        renderer.clear(BLACK);
+        RenderSystem::process(&mut renderer, &world).unwrap();
+        renderer.present().unwrap();
+
+        assert_eq!(renderer.pixel(2, 2), [255, 0, 0, 255]);
+        assert_eq!(renderer.pixel(11, 6), [255, 0, 0, 255]);
+        assert_eq!(renderer.pixel(12, 2), [0, 0, 0, 255]);
+        assert_eq!(renderer.pixel(2, 7), [0, 0, 0, 255]);
+        assert_eq!(renderer.pixel(25, 20), [0, 0, 255, 255]);
+        assert_eq!(renderer.pixel(21, 20), [0, 0, 255, 255]);
+        assert_eq!(renderer.pixel(31, 20), [0, 0, 0, 255]);
+
+        world.time.alpha = 1.0;
+        renderer.clear(WHITE);
+        RenderSystem::process(&mut renderer, &world).unwrap();
+        assert_eq!(renderer.pixel(31, 20), [0, 0, 255, 255]);
+
+        // the camera moves everything the other way
+        world.camera.teleport(Vector::new(10.0, 0.0));
+        renderer.clear(BLACK);
+        RenderSystem::process(&mut renderer, &world).unwrap();
+        assert_eq!(renderer.pixel(1, 6), [255, 0, 0, 255]);
+        assert_eq!(renderer.pixel(2, 6), [0, 0, 0, 255]);
+        assert_eq!(renderer.pixel(21, 20), [0, 0, 255, 255]);
+        assert_eq!(world.screen_to_world(Point::new(21.0, 20.0)), Point::new(31.0, 20.0));
+    }
+
+    #[test]
+    fn layers_and_z_order() {
+        let mut world = World::new();
+        world.screen = Rect::new(0.0, 0.0, 10.0, 10.0);
+        let square = |world: &mut World, colour: Color| {
+            let e = world.spawn();
+            world.set(e, Position { x: 0.0, y: 0.0 });
+            world.set(e, Shape { shape_type: ShapeType::Rectangle(4.0, 4.0), colour });
+            e
+        };
+        let draw = |world: &World| {
+            let mut renderer = SoftwareRenderer::new(10, 10);
+            renderer.clear(BLACK);
+            RenderSystem::process(&mut renderer, world).unwrap();
+            renderer.pixel(1, 1)
+        };
+        let red = Color::from_rgb(255, 0, 0);
+        let green = Color::from_rgb(0, 255, 0);
+        let blue = Color::from_rgb(0, 0, 255);
+
+        let ui = square(&mut world, red);
+        world.set(ui, RenderLayer::Ui);
+        let top = square(&mut world, green);
+        world.set(top, ZIndex(1));
+        let bottom = square(&mut world, blue);
+        world.set(bottom, ZIndex(-1));
+        assert_eq!(draw(&world), [255, 0, 0, 255]);
+
+        world.render_layers.toggle(RenderLayer::Ui);
+        assert!(!world.render_layers.is_visible(RenderLayer::Ui));
+        assert_eq!(draw(&world), [0, 255, 0, 255]);
+        world.set(bottom, RenderLayer::Debug);
+        assert_eq!(draw(&world), [0, 0, 255, 255]);
+        world.render_layers.set_visible(RenderLayer::Debug, false);
+        world.render_layers.set_visible(RenderLayer::Ui, true);
+
+        // the ui stays put when the camera moves, the world doesn't
+        world.camera.teleport(Vector::new(-5.0, 0.0));
+        let mut renderer = SoftwareRenderer::new(10, 10);
+        renderer.clear(BLACK);
+        RenderSystem::process(&mut renderer, &world).unwrap();
+        assert_eq!(renderer.pixel(1, 1), [255, 0, 0, 255]);
+        assert_eq!(renderer.pixel(6, 1), [0, 255, 0, 255]);
+        assert!(RenderLayer::ALL.iter().all(|l| (*l == RenderLayer::Debug) != world.render_layers.is_visible(*l)));
+    }
+
+    #[test]
+    fn sprites_are_drawn_instead_of_shapes() {
+        let mut world = World::new();
+        world.screen = Rect::new(0.0, 0.0, 10.0, 10.0);
+        let (red, green) = ([255, 0, 0, 255], [0, 255, 0, 255]);
+        // a 4x1 sheet of 2x1 frames
+        let sheet = world.images.add(ImageData::new(4, 1, [red, red, green, red].concat()));
+        let sprite = Sprite { scale: 2.0, ..Sprite::new(sheet).frame(2, 1, 1, 0) };
+        assert_eq!(sprite.source, Rect::new(0.5, 0.0, 0.5, 1.0));
+
+        let e = world.spawn();
+        world.set(e, Position { x: 5.0, y: 5.0 });
+        world.set(e, Shape { shape_type: ShapeType::Rectangle(4.0, 2.0), colour: WHITE });
+        world.set(e, sprite);
+        world.set(e, Anchor::CENTER);
+        let quad = sprite.quad(Point::new(5.0, 5.0), 0.0, Anchor::CENTER, &world.images).unwrap();
+        assert_eq!((quad.size, quad.pivot), (Vector::new(4.0, 2.0), Vector::new(0.5, 0.5)));
+        // drawn after the sprite with the same z index, but shapes go first
+        let under = world.spawn();
+        world.set(under, Position { x: 0.0, y: 0.0 });
+        world.set(under, Shape { shape_type: ShapeType::Rectangle(10.0, 10.0), colour: Color::from_rgb(0, 0, 255) });
+
+        let draw = |world: &World| {
+            let mut renderer = SoftwareRenderer::new(10, 10);
+            renderer.clear(BLACK);
+            RenderSystem::process(&mut renderer, world).unwrap();
+            renderer
+        };
+        let renderer = draw(&world);
+        assert_eq!((renderer.pixel(3, 4), renderer.pixel(4, 5), renderer.pixel(5, 4), renderer.pixel(6, 5)), (green, green, red, red));
+        assert_eq!(renderer.pixel(2, 4), [0, 0, 255, 255]);
+
+        // nothing to draw without the image
+        world.set(e, Sprite::new(ImageHandle(9)));
+        assert_eq!(draw(&world).pixel(5, 5), [0, 0, 255, 255]);
+        assert_eq!(Sprite::new(ImageHandle(9)).quad(Point::origin(), 0.0, Anchor::Origin, &world.images), None);
+    }
+}
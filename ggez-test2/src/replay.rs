@@ -0,0 +1,274 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use ggez::event::MouseButton;
+use ggez::graphics::Rect;
+
+use crate::checksum::WorldChecksum;
+
+const HEADER: &str = "ggez-test2 replay 2";
+
+/// Input that reaches the simulation, recorded so a run can be played back
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputEvent {
+    MouseDown { button: MouseButton, x: f32, y: f32 },
+    /// keys are kept by name, they are only recorded for reference
+    KeyDown { key: String },
+    Resize { width: f32, height: f32 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimedInput {
+    /// the input is applied right before this tick is simulated
+    pub tick: u64,
+    pub event: InputEvent,
+}
+
+/// Everything needed to play a run back: the starting conditions, every input with the tick it
+/// was applied on, and the world checksum after each tick to verify the playback against
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recording {
+    pub seed: u64,
+    pub screen: Rect,
+    pub inputs: Vec<TimedInput>,
+    /// `checksums[n]` is the world checksum after tick `n` ran
+    pub checksums: Vec<WorldChecksum>,
+}
+
+impl Recording {
+    pub fn new(seed: u64, screen: Rect) -> Self {
+        Recording {
+            seed,
+            screen,
+            inputs: Vec::new(),
+            checksums: Vec::new(),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        self.write_to(&mut w)?;
+        w.flush()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Recording> {
+        Recording::read_from(BufReader::new(File::open(path)?))
+    }
+
+    /// Writes the line based text format. Floats use Rust's shortest round-trip formatting so
+    /// they read back bit for bit.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "{}", HEADER)?;
+        writeln!(w, "seed {}", self.seed)?;
+        writeln!(w, "screen {:?} {:?} {:?} {:?}", self.screen.x, self.screen.y, self.screen.w, self.screen.h)?;
+        for input in &self.inputs {
+            match &input.event {
+                InputEvent::MouseDown { button, x, y } => {
+                    writeln!(w, "input {} mouse_down {} {:?} {:?}", input.tick, button_name(*button), x, y)?
+                },
+                InputEvent::KeyDown { key } => writeln!(w, "input {} key_down {}", input.tick, key)?,
+                InputEvent::Resize { width, height } => {
+                    writeln!(w, "input {} resize {:?} {:?}", input.tick, width, height)?
+                },
+            }
+        }
+        for (tick, checksum) in self.checksums.iter().enumerate() {
+            write!(w, "checksum {} {:016x}", tick, checksum.total)?;
+            for (name, hash) in &checksum.components {
+                write!(w, " {}={:016x}", name, hash)?;
+            }
+            writeln!(w)?;
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: BufRead>(r: R) -> io::Result<Recording> {
+        let mut lines = r.lines();
+        match lines.next() {
+            Some(line) if line.as_ref().map(|l| l == HEADER).unwrap_or(false) => (),
+            _ => return Err(invalid("not a replay file")),
+        }
+
+        let mut recording = Recording::new(0, Rect::default());
+        for line in lines {
+            let line = line?;
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                [] => (),
+                ["seed", seed] => recording.seed = parse(seed)?,
+                ["screen", x, y, w, h] => recording.screen = Rect::new(parse(x)?, parse(y)?, parse(w)?, parse(h)?),
+                ["input", tick, "mouse_down", button, x, y] => recording.inputs.push(TimedInput {
+                    tick: parse(tick)?,
+                    event: InputEvent::MouseDown { button: parse_button(button)?, x: parse(x)?, y: parse(y)? },
+                }),
+                ["input", tick, "key_down", key] => recording.inputs.push(TimedInput {
+                    tick: parse(tick)?,
+                    event: InputEvent::KeyDown { key: key.to_string() },
+                }),
+                ["input", tick, "resize", width, height] => recording.inputs.push(TimedInput {
+                    tick: parse(tick)?,
+                    event: InputEvent::Resize { width: parse(width)?, height: parse(height)? },
+                }),
+                ["checksum", tick, total, components @ ..] => {
+                    let tick: usize = parse(tick)?;
+                    if tick != recording.checksums.len() {
+                        return Err(invalid(&format!("checksum for tick {} is out of order", tick)));
+                    }
+                    let mut checksum = WorldChecksum { total: parse_hex(total)?, components: Vec::new() };
+                    for component in components {
+                        let mut parts = component.splitn(2, '=');
+                        match (parts.next(), parts.next()) {
+                            (Some(name), Some(hash)) => checksum.components.push((name.to_string(), parse_hex(hash)?)),
+                            _ => return Err(invalid(component)),
+                        }
+                    }
+                    recording.checksums.push(checksum);
+                },
+                _ => return Err(invalid(&line)),
+            }
+        }
+
+        Ok(recording)
+    }
+}
+
+/// Playback state of a `Recording`
+#[derive(Debug, Clone)]
+pub struct Replay {
+    pub recording: Recording,
+    next_input: usize,
+}
+
+/// A replayed tick produced a different world than the recorded one
+#[derive(Debug, Clone, PartialEq)]
+pub struct Desync {
+    pub tick: u64,
+    pub expected: u64,
+    pub found: u64,
+    /// component types whose values differ from the recording
+    pub components: Vec<String>,
+}
+
+impl Replay {
+    pub fn new(recording: Recording) -> Self {
+        Replay {
+            recording,
+            next_input: 0,
+        }
+    }
+
+    /// Inputs recorded for `tick`, in the order they were received
+    pub fn inputs_for(&mut self, tick: u64) -> Vec<InputEvent> {
+        let mut events = Vec::new();
+        while let Some(input) = self.recording.inputs.get(self.next_input) {
+            if input.tick > tick {
+                break;
+            }
+            events.push(input.event.clone());
+            self.next_input += 1;
+        }
+        events
+    }
+
+    /// Compares the checksum after `tick` with the recorded one. Ticks past the end of the
+    /// recording can't be checked and always pass.
+    pub fn verify(&self, tick: u64, checksum: &WorldChecksum) -> Result<(), Desync> {
+        match self.recording.checksums.get(tick as usize) {
+            Some(expected) if expected.total != checksum.total => Err(Desync {
+                tick,
+                expected: expected.total,
+                found: checksum.total,
+                components: expected.differing_components(checksum),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn is_finished(&self, tick: u64) -> bool {
+        tick >= self.recording.checksums.len() as u64
+    }
+}
+
+fn button_name(button: MouseButton) -> String {
+    match button {
+        MouseButton::Left => "left".to_string(),
+        MouseButton::Right => "right".to_string(),
+        MouseButton::Middle => "middle".to_string(),
+        MouseButton::Other(n) => format!("other{}", n),
+    }
+}
+
+fn parse_button(s: &str) -> io::Result<MouseButton> {
+    match s {
+        "left" => Ok(MouseButton::Left),
+        "right" => Ok(MouseButton::Right),
+        "middle" => Ok(MouseButton::Middle),
+        _ if s.starts_with("other") => Ok(MouseButton::Other(parse(&s[5..])?)),
+        _ => Err(invalid(s)),
+    }
+}
+
+fn parse<T: std::str::FromStr>(s: &str) -> io::Result<T> {
+    s.parse().map_err(|_| invalid(s))
+}
+
+fn parse_hex(s: &str) -> io::Result<u64> {
+    u64::from_str_radix(s, 16).map_err(|_| invalid(s))
+}
+
+fn invalid(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("bad replay data: {}", what))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recording() -> Recording {
+        let mut r = Recording::new(99, Rect::new(0.0, 0.0, 1280.0, 1024.0));
+        r.inputs.push(TimedInput { tick: 3, event: InputEvent::MouseDown { button: MouseButton::Left, x: 0.1, y: 1.0 / 3.0 } });
+        r.inputs.push(TimedInput { tick: 3, event: InputEvent::KeyDown { key: "D".to_string() } });
+        r.inputs.push(TimedInput { tick: 7, event: InputEvent::Resize { width: 800.0, height: 600.0 } });
+        r.checksums = vec![checksum(1, 1), checksum(2, 2), checksum(0xdead_beef, 3), checksum(u64::MAX, 4)];
+        r
+    }
+
+    fn checksum(total: u64, position: u64) -> WorldChecksum {
+        WorldChecksum { total, components: vec![("Position".to_string(), position), ("Velocity".to_string(), 0)] }
+    }
+
+    #[test]
+    fn text_format_round_trips() {
+        let r = recording();
+        let mut buf = Vec::new();
+        r.write_to(&mut buf).unwrap();
+
+        assert_eq!(Recording::read_from(&buf[..]).unwrap(), r);
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        assert!(Recording::read_from(&b"hello"[..]).is_err());
+        assert!(Recording::read_from(format!("{}\nseed x\n", HEADER).as_bytes()).is_err());
+        assert!(Recording::read_from(format!("{}\nchecksum 1 ff\n", HEADER).as_bytes()).is_err());
+        assert!(Recording::read_from(format!("{}\nchecksum 0 ff Position\n", HEADER).as_bytes()).is_err());
+    }
+
+    #[test]
+    fn replay_hands_out_inputs_by_tick() {
+        let mut replay = Replay::new(recording());
+
+        assert!(replay.inputs_for(0).is_empty());
+        assert_eq!(replay.inputs_for(3).len(), 2);
+        assert!(replay.inputs_for(3).is_empty());
+        assert_eq!(replay.inputs_for(10), vec![InputEvent::Resize { width: 800.0, height: 600.0 }]);
+
+        assert_eq!(replay.verify(2, &checksum(0xdead_beef, 3)), Ok(()));
+        assert_eq!(
+            replay.verify(1, &checksum(5, 7)),
+            Err(Desync { tick: 1, expected: 2, found: 5, components: vec!["Position".to_string()] })
+        );
+        assert_eq!(replay.verify(100, &checksum(5, 7)), Ok(()));
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+
+use rand::SeedableRng;
+use rand_pcg::Pcg32;
+
+use crate::checksum::StableHasher;
+
+pub type StreamRng = Pcg32;
+
+/// World-owned source of randomness. Every system draws from its own named stream, derived
+/// from the world seed and the stream name, so adding a system or changing how many numbers
+/// one system draws never shifts the sequences seen by the others.
+#[derive(Debug, Clone)]
+pub struct Random {
+    seed: u64,
+    streams: HashMap<&'static str, StreamRng>,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        Random {
+            seed,
+            streams: HashMap::new(),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// The generator for `name`, created on first use
+    pub fn stream(&mut self, name: &'static str) -> &mut StreamRng {
+        let seed = self.seed;
+        self.streams.entry(name).or_insert_with(|| StreamRng::seed_from_u64(derive_seed(seed, name)))
+    }
+}
+
+/// Mixes the stream name into the seed. `StableHasher` followed by a splitmix64 finaliser, both of
+/// which are fixed so seeds stay stable across builds and platforms.
+fn derive_seed(seed: u64, name: &str) -> u64 {
+    let mut hasher = StableHasher::new();
+    hasher.write_bytes(name.as_bytes());
+
+    let mut z = seed ^ hasher.finish();
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    fn draw(random: &mut Random, name: &'static str) -> Vec<u32> {
+        (0..8).map(|_| random.stream(name).gen()).collect()
+    }
+
+    #[test]
+    fn same_seed_same_sequence() {
+        let mut a = Random::new(42);
+        let mut b = Random::new(42);
+
+        assert_eq!(draw(&mut a, "collision"), draw(&mut b, "collision"));
+        assert_ne!(draw(&mut a, "collision"), draw(&mut Random::new(43), "collision"));
+    }
+
+    #[test]
+    fn streams_are_independent() {
+        let mut a = Random::new(7);
+        let mut b = Random::new(7);
+
+        // b uses another stream in between, which must not affect the collision stream
+        let first = draw(&mut a, "collision");
+        draw(&mut b, "spawn");
+        assert_eq!(first, draw(&mut b, "collision"));
+        assert_ne!(draw(&mut a, "spawn"), draw(&mut a, "collision"));
+    }
+
+    #[test]
+    fn derived_seeds_are_pinned() {
+        // recorded replays depend on this never changing
+        assert_eq!(derive_seed(42, "spawn"), 0xbc2a_dd14_e056_03bb);
+    }
+}
@@ -0,0 +1,291 @@
+use std::time::Duration;
+
+use ggez::graphics::{Color, Rect};
+use rand::prelude::*;
+
+use crate::components::*;
+use crate::replay::*;
+use crate::rng::Random;
+use crate::systems::*;
+use crate::timestep::*;
+use crate::world::*;
+
+pub const SIMULATION_HZ: f32 = 60.0;
+pub const MAX_CATCH_UP_STEPS: u32 = 5;
+/// Particles hit obstacles and the screen edges, but pass through each other
+pub const PARTICLE_LAYER: u32 = 1 << 1;
+
+/// The world plus the clock that steps it. Owned by the windowed `GameState` and by the
+/// headless runner alike.
+#[derive(Debug)]
+pub struct Simulation {
+    pub world: World,
+    pub timestep: FixedTimestep,
+    /// inputs received since the last step, applied at the start of the next one
+    pending_inputs: Vec<InputEvent>,
+    /// set while recording, collects the inputs and checksums of every tick
+    pub recording: Option<Recording>,
+    /// set while playing back, live input is ignored and inputs come from the replay instead
+    pub replay: Option<Replay>,
+    /// the first tick that did not match the replay's checksum
+    pub desync: Option<Desync>,
+}
+
+impl Simulation {
+    /// Creates an empty simulation confined to `screen`, which for headless runs is a
+    /// virtual screen rect. Runs with the same seed and inputs play out identically.
+    pub fn new(screen: Rect, seed: u64) -> Self {
+        let timestep = FixedTimestep::new(SIMULATION_HZ).with_max_steps(MAX_CATCH_UP_STEPS);
+        let mut world = World::new();
+        world.time.dt = timestep.dt();
+        world.screen = screen;
+        world.rng = Random::new(seed);
+
+        Simulation {
+            world,
+            timestep,
+            pending_inputs: Vec::new(),
+            recording: None,
+            replay: None,
+            desync: None,
+        }
+    }
+
+    /// Creates a simulation that plays `recording` back. It starts from the same seed, screen
+    /// and initial entities as the recorded run.
+    pub fn from_recording(recording: Recording) -> Self {
+        let mut sim = Simulation::new(recording.screen, recording.seed);
+        sim.populate();
+        sim.replay = Some(Replay::new(recording));
+        sim
+    }
+
+    /// Starts recording inputs and checksums. Call before `populate` so the recording
+    /// starts from an empty world.
+    pub fn start_recording(&mut self) {
+        self.recording = Some(Recording::new(self.world.rng.seed(), self.world.screen));
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        self.replay.is_some()
+    }
+
+    /// Spawns the initial particles and the obstacle in the middle of the screen
+    pub fn populate(&mut self) {
+        self.generate_entities();
+        let screen = self.world.screen;
+        self.spawn_obstacle(screen.x + screen.w / 2.0, screen.y + screen.h / 2.0);
+    }
+
+    /// Queues an input for the next step. Live input is dropped while a replay is running.
+    pub fn queue_input(&mut self, event: InputEvent) {
+        if !self.is_replaying() {
+            self.pending_inputs.push(event);
+        }
+    }
+
+    fn apply_input(&mut self, event: &InputEvent) {
+        match event {
+            InputEvent::MouseDown { .. } => self.generate_entities(),
+            InputEvent::KeyDown { .. } => (),
+            InputEvent::Resize { width, height } => self.world.screen = Rect::new(0.0, 0.0, *width, *height),
+        }
+    }
+
+    /// Runs one fixed step of the simulation schedule
+    pub fn step(&mut self) {
+        let tick = self.world.time.tick;
+
+        if let Some(replay) = &mut self.replay {
+            let inputs = replay.inputs_for(tick);
+            self.pending_inputs.extend(inputs);
+        }
+        for event in std::mem::take(&mut self.pending_inputs) {
+            if let Some(recording) = &mut self.recording {
+                recording.inputs.push(TimedInput { tick, event: event.clone() });
+            }
+            self.apply_input(&event);
+        }
+
+        MovementSystem::process(&mut self.world);
+        ContactSystem::process(&mut self.world);
+        CollissionSystem::process(&mut self.world);
+        SleepSystem::process(&mut self.world);
+
+        self.world.apply_commands();
+        SpatialIndexSystem::process(&mut self.world);
+        CameraSystem::process(&mut self.world);
+
+        if self.recording.is_some() || self.replay.is_some() {
+            let checksum = self.world.checksum_breakdown();
+            if let Some(replay) = &self.replay {
+                if let Err(desync) = replay.verify(tick, &checksum) {
+                    self.desync.get_or_insert(desync);
+                }
+            }
+            if let Some(recording) = &mut self.recording {
+                recording.checksums.push(checksum);
+            }
+        }
+
+        self.world.time.tick += 1;
+    }
+
+    /// Runs as many fixed steps as fit into `frame_time` and updates the interpolation alpha
+    pub fn advance(&mut self, frame_time: Duration) {
+        let steps = self.timestep.advance(frame_time);
+        for _ in 0..steps {
+            self.step();
+        }
+        self.world.time.alpha = self.timestep.alpha();
+    }
+
+    /// Spawns particles at random spots on the screen, whatever size it is
+    pub fn generate_entities(&mut self) {
+        let screen = self.world.screen;
+        for _ in 0..1000 {
+            let rng = self.world.rng.stream("spawn");
+            let position = Position{ x: screen.x + rng.gen::<f32>() * screen.w, y: screen.y + rng.gen::<f32>() * screen.h };
+            let velocity = Velocity{ xv: 60.0 + rng.gen::<f32>() * 900.0, yv: 0.0 };
+            let alpha = rng.gen::<u8>();
+
+            let shape_type = ShapeType::Circle(4.0);
+
+            let e = self.world.spawn();
+            self.world.set(e, position);
+            self.world.set(e, velocity);
+            self.world.set(e, Shape{ 
+                shape_type, 
+                colour: Color::from_rgba(255, 255, 255, alpha)
+            });
+            self.world.set(e, RigidBody::dynamic_from_shape(&shape_type, &PhysicsMaterial::default()));
+            self.world.set(e, Sleep::default());
+            // fast enough to skip past an obstacle's corner in a single step
+            self.world.set(e, Ccd);
+            self.world.set(e, CollisionFilter::new(PARTICLE_LAYER, CollisionFilter::ALL_LAYERS & !PARTICLE_LAYER));
+            self.world.set(e, Particle);
+        }
+    }
+
+    pub fn spawn_obstacle(&mut self, x: f32, y: f32) -> Entity {
+        let e = self.world.spawn();
+        self.world.set(e, Position{ x, y });
+        self.world.set(e, Shape{ shape_type: ShapeType::Rectangle(20.0, 20.0), colour: Color::from_rgb(255, 128, 128)});
+        self.world.set(e, RigidBody::fixed());
+        self.world.set(e, Name::new("obstacle"));
+        // on top of the particles passing over it, whichever entities they are
+        self.world.set(e, ZIndex(1));
+        self.world.set(e, Obstacle);
+        e
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ggez::event::MouseButton;
+
+    fn entity_states(sim: &Simulation) -> Vec<(Entity, Position, Velocity)> {
+        let world = &sim.world;
+        world.query_filtered::<With<Velocity>>()
+            .map(|e| (e, *world.get::<Position>(e).unwrap(), *world.get::<Velocity>(e).unwrap()))
+            .collect()
+    }
+
+    fn run(seed: u64, ticks: u64) -> Simulation {
+        let mut sim = Simulation::new(Rect::new(0.0, 0.0, 1280.0, 1024.0), seed);
+        sim.start_recording();
+        sim.populate();
+        for tick in 1..=ticks {
+            if tick % 50 == 0 {
+                sim.queue_input(InputEvent::MouseDown { button: MouseButton::Left, x: 10.0, y: 10.0 });
+            }
+            if tick == 100 {
+                // growing the screen, shrinking it would crush every particle outside into its edges
+                sim.queue_input(InputEvent::Resize { width: 1600.0, height: 1200.0 });
+            }
+            sim.step();
+        }
+        sim
+    }
+
+    #[test]
+    fn same_seed_is_deterministic() {
+        let a = run(1234, 150);
+        let b = run(1234, 150);
+        let c = run(4321, 150);
+
+        assert!(!entity_states(&a).is_empty());
+        assert_eq!(entity_states(&a), entity_states(&b));
+        assert_ne!(entity_states(&a), entity_states(&c));
+    }
+
+    #[test]
+    fn replay_matches_recording() {
+        let recorded = run(55, 120);
+        let recording = recorded.recording.clone().unwrap();
+        assert_eq!(recording.checksums.len(), 120);
+        assert_eq!(recording.inputs.len(), 3);
+
+        let mut replayed = Simulation::from_recording(recording.clone());
+        // live input must not leak into a replay
+        replayed.queue_input(InputEvent::MouseDown { button: MouseButton::Left, x: 0.0, y: 0.0 });
+        while !replayed.replay.as_ref().unwrap().is_finished(replayed.world.time.tick) {
+            replayed.step();
+        }
+        assert_eq!(replayed.desync, None);
+        assert_eq!(entity_states(&recorded), entity_states(&replayed));
+
+        // a replay with a dropped input has to be caught
+        let mut tampered = recording;
+        tampered.inputs.remove(1);
+        let mut replayed = Simulation::from_recording(tampered);
+        for _ in 0..120 {
+            replayed.step();
+        }
+        let desync = replayed.desync.unwrap();
+        assert_eq!(desync.tick, 99);
+        assert!(desync.components.contains(&"Position".to_string()));
+    }
+
+    fn assert_on_screen(sim: &Simulation) {
+        for e in sim.world.query_filtered::<With<Particle>>() {
+            let p = sim.world.get::<Position>(e).unwrap();
+            assert!(p.x >= 0.0 && p.x <= 320.0 && p.y >= 0.0 && p.y <= 240.0, "{:?} left the screen", p);
+        }
+    }
+
+    #[test]
+    fn runs_without_a_context() {
+        let mut sim = Simulation::new(Rect::new(0.0, 0.0, 320.0, 240.0), 0);
+        sim.generate_entities();
+        sim.spawn_obstacle(100.0, 100.0);
+        // particles spawn on the virtual screen, not on the default window size
+        assert_on_screen(&sim);
+
+        for _ in 0..120 {
+            sim.step();
+        }
+
+        assert_eq!(sim.world.time.tick, 120);
+        assert_on_screen(&sim);
+    }
+
+    #[test]
+    fn particles_only_hit_obstacles() {
+        let mut sim = Simulation::new(Rect::new(0.0, 0.0, 320.0, 240.0), 0);
+        sim.generate_entities();
+        let obstacle = sim.spawn_obstacle(100.0, 100.0);
+
+        let mut obstacle_hits = 0;
+        for _ in 0..60 {
+            sim.step();
+            let world = &sim.world;
+            for manifold in &world.contacts {
+                assert!(!(world.has::<Particle>(manifold.a) && world.has::<Particle>(manifold.b)));
+            }
+            obstacle_hits += world.contacts.iter().filter(|m| m.a == obstacle || m.b == obstacle).count();
+        }
+        assert!(obstacle_hits > 0);
+    }
+}
@@ -0,0 +1,230 @@
+//! Golden image tests. Scenes are drawn with the `SoftwareRenderer` and compared with the PNGs
+//! in `snapshots/`. A scene that doesn't match leaves `<name>.actual.png` and `<name>.diff.png`
+//! next to its golden image. Run the tests with `UPDATE_SNAPSHOTS=1` to write new golden images
+//! after a change to the drawing that was meant to happen.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+use ggez::graphics::{Color, Rect, WHITE};
+
+use crate::collision::*;
+use crate::components::*;
+use crate::images::*;
+use crate::render::*;
+use crate::simulation::*;
+use crate::software::SoftwareRenderer;
+use crate::world::*;
+
+/// How far an image may be from its golden image and still match
+#[derive(Debug, Copy, Clone)]
+struct Tolerance {
+    /// a pixel only counts as different if one of its channels is further off than this
+    channel: u8,
+    /// how many different pixels are let through
+    pixels: usize,
+}
+
+fn snapshot_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("snapshots")
+}
+
+fn write_png(path: &Path, width: usize, height: usize, pixels: &[u8]) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width as u32, height as u32);
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    writer.write_image_data(pixels).map_err(io::Error::other)
+}
+
+/// Width, height and RGBA pixels of an 8 bit RGBA image
+fn read_png(path: &Path) -> io::Result<(usize, usize, Vec<u8>)> {
+    let decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+    let (info, mut reader) = decoder.read_info().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    if info.color_type != png::ColorType::RGBA || info.bit_depth != png::BitDepth::Eight {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not an 8 bit RGBA image"));
+    }
+    let mut pixels = vec![0; info.buffer_size()];
+    reader.next_frame(&mut pixels).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok((info.width as usize, info.height as usize, pixels))
+}
+
+/// The number of pixels that differ by more than `channel`, and an image showing them in red
+/// over a faded copy of `expected`
+fn compare(expected: &[u8], actual: &[u8], channel: u8) -> (usize, Vec<u8>) {
+    let mut differing = 0;
+    let mut diff = Vec::with_capacity(expected.len());
+    for (e, a) in expected.chunks(4).zip(actual.chunks(4)) {
+        if e.iter().zip(a).any(|(e, a)| (*e as i16 - *a as i16).abs() > channel as i16) {
+            differing += 1;
+            diff.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            let grey = ((e[0] as u16 + e[1] as u16 + e[2] as u16) / 12) as u8;
+            diff.extend_from_slice(&[grey, grey, grey, 255]);
+        }
+    }
+    (differing, diff)
+}
+
+/// Compares what `renderer` has drawn with the golden image `snapshots/<name>.png`
+fn assert_snapshot(name: &str, renderer: &SoftwareRenderer, tolerance: Tolerance) {
+    let dir = snapshot_dir();
+    let golden = dir.join(format!("{}.png", name));
+    let actual_path = dir.join(format!("{}.actual.png", name));
+    let diff_path = dir.join(format!("{}.diff.png", name));
+    let (width, height, actual) = (renderer.width(), renderer.height(), renderer.pixels());
+
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        fs::create_dir_all(&dir).unwrap();
+        write_png(&golden, width, height, actual).unwrap();
+        return;
+    }
+
+    let (expected_width, expected_height, expected) = match read_png(&golden) {
+        Ok(image) => image,
+        Err(e) => panic!("could not read {}: {}, run with UPDATE_SNAPSHOTS=1 to write it", golden.display(), e),
+    };
+    if (expected_width, expected_height) != (width, height) {
+        write_png(&actual_path, width, height, actual).unwrap();
+        panic!("{} is {}x{} but {}x{} was drawn, see {}", golden.display(), expected_width, expected_height,
+            width, height, actual_path.display());
+    }
+
+    let (differing, diff) = compare(&expected, actual, tolerance.channel);
+    if differing > tolerance.pixels {
+        write_png(&actual_path, width, height, actual).unwrap();
+        write_png(&diff_path, width, height, &diff).unwrap();
+        panic!("{} pixels differ from {}, see {} and {}", differing, golden.display(),
+            actual_path.display(), diff_path.display());
+    }
+    // left behind by an earlier failure
+    let _ = fs::remove_file(actual_path);
+    let _ = fs::remove_file(diff_path);
+}
+
+fn spawn(world: &mut World, x: f32, y: f32, shape_type: ShapeType, colour: Color) -> Entity {
+    let e = world.spawn();
+    world.set(e, Position { x, y });
+    world.set(e, Shape { shape_type, colour });
+    e
+}
+
+#[test]
+fn compare_counts_pixels_outside_the_tolerance() {
+    let expected = [12, 12, 12, 255, 100, 100, 100, 255];
+    assert_eq!(compare(&expected, &expected, 0), (0, vec![3, 3, 3, 255, 25, 25, 25, 255]));
+
+    let actual = [14, 12, 12, 255, 100, 100, 100, 253];
+    assert_eq!(compare(&expected, &actual, 2).0, 0);
+    assert_eq!(compare(&expected, &actual, 1), (2, vec![255, 0, 0, 255, 255, 0, 0, 255]));
+}
+
+#[test]
+fn png_round_trip() {
+    let path = std::env::temp_dir().join(format!("ggez-test2-snapshot-{}.png", std::process::id()));
+    let pixels = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];
+    write_png(&path, 3, 1, &pixels).unwrap();
+    assert_eq!(read_png(&path).unwrap(), (3, 1, pixels.to_vec()));
+    fs::remove_file(&path).unwrap();
+}
+
+/// Every kind of shape, turned and anchored, so a change to where any of them is drawn shows up
+#[test]
+fn every_shape() {
+    let mut world = World::new();
+    world.screen = Rect::new(0.0, 0.0, 160.0, 120.0);
+    spawn(&mut world, 10.0, 10.0, ShapeType::Rectangle(30.0, 20.0), Color::from_rgb(255, 96, 96));
+    spawn(&mut world, 70.0, 20.0, ShapeType::Circle(12.0), Color::from_rgb(96, 255, 96));
+    let polygon = ConvexPolygon::regular(5, 14.0).unwrap();
+    spawn(&mut world, 120.0, 22.0, ShapeType::Polygon(polygon), Color::from_rgb(96, 96, 255));
+    let turned = spawn(&mut world, 30.0, 70.0, ShapeType::Rectangle(30.0, 12.0), Color::from_rgb(255, 255, 96));
+    world.set(turned, Rotation { angle: 0.5 });
+    world.set(turned, Anchor::CENTER);
+    let capsule = spawn(&mut world, 85.0, 70.0, ShapeType::Capsule(30.0, 6.0), Color::from_rgb(96, 255, 255));
+    world.set(capsule, Rotation { angle: -0.3 });
+    spawn(&mut world, 130.0, 70.0, ShapeType::Segment(40.0), WHITE);
+    let centred = spawn(&mut world, 120.0, 105.0, ShapeType::Rectangle(40.0, 16.0), Color::from_rgba(255, 96, 255, 128));
+    world.set(centred, Anchor::CENTER);
+
+    let mut renderer = SoftwareRenderer::new(160, 120);
+    renderer.clear(Color::from_rgb(32, 32, 48));
+    RenderSystem::process(&mut renderer, &world).unwrap();
+    renderer.draw_text("Shapes 0-9", Point::new(2.0, 108.0), WHITE).unwrap();
+    assert_snapshot("every_shape", &renderer, Tolerance { channel: 1, pixels: 0 });
+}
+
+/// A sheet of two 8x8 frames, an arrow pointing right and a ring, so flips and turns show
+fn sprite_sheet() -> ImageData {
+    let mut pixels = Vec::new();
+    for y in 0..8i32 {
+        for x in 0..16i32 {
+            let (fx, fy) = (x % 8, y);
+            let lit = if x < 8 {
+                // the shaft and the head of the arrow
+                (fy == 3 || fy == 4) && fx < 5 || fx >= 5 && (fy - 3).abs().min((fy - 4).abs()) <= 7 - fx
+            } else {
+                let d = (fx * 2 - 7).pow(2) + (fy * 2 - 7).pow(2);
+                (16..=52).contains(&d)
+            };
+            let colour = match (lit, x < 8) {
+                (true, true) => [255, 200, 64, 255],
+                (true, false) => [64, 200, 255, 255],
+                (false, _) => [0, 0, 0, 0],
+            };
+            pixels.extend_from_slice(&colour);
+        }
+    }
+    ImageData::new(16, 8, pixels)
+}
+
+/// Sprites picked from a sheet, scaled, flipped, turned and tinted, some over shapes
+#[test]
+fn sprites() {
+    let mut world = World::new();
+    world.screen = Rect::new(0.0, 0.0, 160.0, 120.0);
+    let sheet = world.images.add(sprite_sheet());
+    let arrow = Sprite::new(sheet).frame(2, 1, 0, 0);
+    let ring = Sprite::new(sheet).frame(2, 1, 1, 0);
+    let sprite = |world: &mut World, x: f32, y: f32, sprite: Sprite| {
+        let e = world.spawn();
+        world.set(e, Position { x, y });
+        world.set(e, sprite);
+        e
+    };
+
+    sprite(&mut world, 8.0, 8.0, arrow);
+    sprite(&mut world, 24.0, 8.0, Sprite { scale: 3.0, ..arrow });
+    sprite(&mut world, 56.0, 8.0, Sprite { scale: 3.0, flip_x: true, ..arrow });
+    sprite(&mut world, 88.0, 8.0, Sprite { scale: 3.0, flip_y: true, ..ring });
+    let turned = sprite(&mut world, 40.0, 72.0, Sprite { scale: 4.0, ..arrow });
+    world.set(turned, Rotation { angle: 0.6 });
+    world.set(turned, Anchor::CENTER);
+    spawn(&mut world, 80.0, 50.0, ShapeType::Rectangle(48.0, 40.0), Color::from_rgb(96, 96, 160));
+    sprite(&mut world, 88.0, 56.0, Sprite { scale: 3.0, tint: Color::new(1.0, 0.5, 0.5, 0.75), ..ring });
+    let on_top = sprite(&mut world, 130.0, 90.0, Sprite { scale: 5.0, ..ring });
+    world.set(on_top, Anchor::CENTER);
+    world.set(on_top, ZIndex(1));
+    spawn(&mut world, 110.0, 80.0, ShapeType::Circle(12.0), Color::from_rgb(255, 96, 96));
+
+    let mut renderer = SoftwareRenderer::new(160, 120);
+    renderer.clear(Color::from_rgb(32, 32, 48));
+    RenderSystem::process(&mut renderer, &world).unwrap();
+    assert_snapshot("sprites", &renderer, Tolerance { channel: 1, pixels: 0 });
+}
+
+/// A seeded run of the particle simulation, the same scene the game starts with
+#[test]
+fn simulation_at_tick_120() {
+    let mut sim = Simulation::new(Rect::new(0.0, 0.0, 320.0, 240.0), 1234);
+    sim.populate();
+    for _ in 0..120 {
+        sim.step();
+    }
+
+    let mut renderer = SoftwareRenderer::new(320, 240);
+    renderer.clear(Color::from_rgb(32, 32, 48));
+    RenderSystem::process(&mut renderer, &sim.world).unwrap();
+    // a particle landing a step differently shouldn't need a new golden image
+    assert_snapshot("simulation_at_tick_120", &renderer, Tolerance { channel: 1, pixels: 200 });
+}
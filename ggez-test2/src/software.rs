@@ -0,0 +1,387 @@
+use ggez::graphics::Color;
+use ggez::GameResult;
+
+use crate::collision::*;
+use crate::images::ImageData;
+use crate::render::*;
+
+/// Pixels a glyph of the built-in font is scaled up by
+pub const GLYPH_SCALE: usize = 2;
+/// Width and height of a glyph before scaling, plus a pixel of space on the right
+const GLYPH_SIZE: (usize, usize) = (4, 5);
+
+/// Draws into an RGBA buffer on the CPU, without a window or a GPU. A pixel is covered by a
+/// shape if the shape contains its centre, so edges are hard and there is no anti-aliasing.
+/// Sprites take the texel nearest to each pixel centre. Text uses a small built-in font rather
+/// than ggez's.
+#[derive(Debug, Clone)]
+pub struct SoftwareRenderer {
+    width: usize,
+    height: usize,
+    /// 4 bytes per pixel, row by row from the top left
+    pixels: Vec<u8>,
+    view: View,
+}
+
+impl SoftwareRenderer {
+    /// Starts out transparent black
+    pub fn new(width: usize, height: usize) -> Self {
+        SoftwareRenderer { width, height, pixels: vec![0; width * height * 4], view: View::default() }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> [u8; 4] {
+        let i = (y * self.width + x) * 4;
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2], self.pixels[i + 3]]
+    }
+
+    /// Draws `colour` over the pixel, mixing by its alpha
+    fn blend(&mut self, x: usize, y: usize, colour: Color) {
+        let i = (y * self.width + x) * 4;
+        let a = colour.a.clamp(0.0, 1.0);
+        for (c, source) in [colour.r, colour.g, colour.b].iter().enumerate() {
+            let destination = self.pixels[i + c] as f32 / 255.0;
+            self.pixels[i + c] = to_byte(source * a + destination * (1.0 - a));
+        }
+        let destination = self.pixels[i + 3] as f32 / 255.0;
+        self.pixels[i + 3] = to_byte(a + destination * (1.0 - a));
+    }
+
+    /// Covers every pixel whose centre, in screen coordinates, is inside `covered`
+    fn fill(&mut self, min: Point, max: Point, colour: Color, covered: impl Fn(Point) -> bool) {
+        self.paint(min, max, |p| if covered(p) { Some(colour) } else { None });
+    }
+
+    /// Blends in the colour `paint` gives for the centre of each pixel between `min` and `max`,
+    /// in screen coordinates, leaving the ones it gives none for alone
+    fn paint(&mut self, min: Point, max: Point, paint: impl Fn(Point) -> Option<Color>) {
+        let x0 = min.x.floor().max(0.0) as usize;
+        let y0 = min.y.floor().max(0.0) as usize;
+        let x1 = (max.x.ceil().max(0.0) as usize).min(self.width);
+        let y1 = (max.y.ceil().max(0.0) as usize).min(self.height);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                if let Some(colour) = paint(Point::new(x as f32 + 0.5, y as f32 + 0.5)) {
+                    self.blend(x, y, colour);
+                }
+            }
+        }
+    }
+
+    /// The screen box around world points
+    fn screen_bounds(&self, corners: &[Point]) -> (Point, Point) {
+        let corners = corners.iter().map(|p| self.view.to_screen(*p)).collect::<Vec<_>>();
+        let min = corners.iter().fold(corners[0], |m, p| Point::new(m.x.min(p.x), m.y.min(p.y)));
+        let max = corners.iter().fold(corners[0], |m, p| Point::new(m.x.max(p.x), m.y.max(p.y)));
+        (min, max)
+    }
+}
+
+fn to_byte(c: f32) -> u8 {
+    (c.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+impl Renderer for SoftwareRenderer {
+    fn clear(&mut self, colour: Color) {
+        let rgba = [to_byte(colour.r), to_byte(colour.g), to_byte(colour.b), to_byte(colour.a)];
+        for pixel in self.pixels.chunks_mut(4) {
+            pixel.copy_from_slice(&rgba);
+        }
+    }
+
+    fn set_camera(&mut self, view: View) -> GameResult<()> {
+        self.view = view;
+        Ok(())
+    }
+
+    fn draw_shape(&mut self, collider: &Collider, colour: Color) -> GameResult<()> {
+        // the screen box around the corners of the world box, segments are a pixel wide
+        let (min, max) = collider.bounds();
+        let (min, max) = (min - Vector::new(0.5, 0.5), max + Vector::new(0.5, 0.5));
+        let (screen_min, screen_max) = self.screen_bounds(&[min, Point::new(max.x, min.y), max, Point::new(min.x, max.y)]);
+
+        let view = self.view;
+        match *collider {
+            // segments have no area, draw them a pixel wide and with flat ends the way ggez
+            // draws lines. Edges count so a line along the border between two rows still shows.
+            Collider::Capsule { a, b, radius: 0.0 } => {
+                self.fill(screen_min, screen_max, colour, |p| {
+                    let p = view.to_world(p);
+                    let along = (p - a).dot(&(b - a)) / (b - a).norm_squared();
+                    0.0 < along && along < 1.0 && (p - closest_on_segment(p, a, b)).norm() <= 0.5
+                });
+            },
+            collider => self.fill(screen_min, screen_max, colour, |p| collider.contains(view.to_world(p))),
+        }
+        Ok(())
+    }
+
+    fn draw_sprite(&mut self, quad: &SpriteQuad, image: &ImageData) -> GameResult<()> {
+        // `ImageData::new` won't make one, but the fields are public
+        if image.width == 0 || image.height == 0 {
+            return Ok(());
+        }
+        let (sin, cos) = quad.rotation.sin_cos();
+        let place = |x: f32, y: f32| {
+            let local = Vector::new(x - quad.pivot.x, y - quad.pivot.y).component_mul(&quad.size);
+            quad.position + Vector::new(local.x * cos - local.y * sin, local.x * sin + local.y * cos)
+        };
+        let (min, max) = self.screen_bounds(&[place(0.0, 0.0), place(1.0, 0.0), place(1.0, 1.0), place(0.0, 1.0)]);
+
+        let view = self.view;
+        let source = quad.source;
+        self.paint(min, max, |p| {
+            // the pixel centre as a fraction of the quad from its top left corner
+            let local = view.to_world(p) - quad.position;
+            let unturned = Vector::new(local.x * cos + local.y * sin, -local.x * sin + local.y * cos);
+            let u = unturned.x / quad.size.x + quad.pivot.x;
+            let v = unturned.y / quad.size.y + quad.pivot.y;
+            if !(0.0..1.0).contains(&u) || !(0.0..1.0).contains(&v) {
+                return None;
+            }
+            let u = if quad.flip_x { 1.0 - u } else { u };
+            let v = if quad.flip_y { 1.0 - v } else { v };
+            let x = ((source.x + u * source.w) * image.width as f32) as usize;
+            let y = ((source.y + v * source.h) * image.height as f32) as usize;
+            let texel = image.pixel(x.min(image.width - 1), y.min(image.height - 1));
+            let channel = |c: usize| texel[c] as f32 / 255.0;
+            let tint = quad.tint;
+            Some(Color::new(channel(0) * tint.r, channel(1) * tint.g, channel(2) * tint.b, channel(3) * tint.a))
+        });
+        Ok(())
+    }
+
+    fn draw_text(&mut self, text: &str, position: Point, colour: Color) -> GameResult<()> {
+        let (width, height) = GLYPH_SIZE;
+        let mut x = position.x;
+        for c in text.chars() {
+            let rows = glyph(c);
+            let origin = Point::new(x, position.y);
+            let size = Vector::new(width as f32, height as f32) * GLYPH_SCALE as f32;
+            self.fill(origin, origin + size, colour, |p| {
+                let column = ((p.x - origin.x) / GLYPH_SCALE as f32) as usize;
+                let row = ((p.y - origin.y) / GLYPH_SCALE as f32) as usize;
+                column < 3 && row < height && rows[row] & (0b100 >> column) != 0
+            });
+            x += size.x;
+        }
+        Ok(())
+    }
+
+    /// The pixels are read straight from the buffer, so there is nothing to show
+    fn present(&mut self) -> GameResult<()> {
+        Ok(())
+    }
+}
+
+/// Rows of a 3x5 glyph from the top, the highest of the 3 bits is the left column. Lower
+/// case letters are drawn as upper case, anything the font doesn't have as a `?`.
+fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_uppercase() {
+        ' ' => [0b000, 0b000, 0b000, 0b000, 0b000],
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+        '(' => [0b001, 0b010, 0b010, 0b010, 0b001],
+        ')' => [0b100, 0b010, 0b010, 0b010, 0b100],
+        '!' => [0b010, 0b010, 0b010, 0b000, 0b010],
+        _ => [0b111, 0b001, 0b011, 0b000, 0b010],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::images::ImageHandle;
+    use ggez::graphics::{Rect, BLACK, WHITE};
+
+    fn renderer() -> SoftwareRenderer {
+        let mut renderer = SoftwareRenderer::new(20, 10);
+        renderer.clear(BLACK);
+        renderer
+    }
+
+    fn covered(renderer: &SoftwareRenderer) -> Vec<(usize, usize)> {
+        let mut pixels = Vec::new();
+        for y in 0..renderer.height() {
+            for x in 0..renderer.width() {
+                if renderer.pixel(x, y) != [0, 0, 0, 255] {
+                    pixels.push((x, y));
+                }
+            }
+        }
+        pixels
+    }
+
+    #[test]
+    fn shapes_cover_the_pixels_whose_centre_they_contain() {
+        let mut r = renderer();
+        r.draw_shape(&Collider::Aabb { min: Point::new(1.0, 1.0), max: Point::new(3.0, 2.0) }, WHITE).unwrap();
+        assert_eq!(covered(&r), vec![(1, 1), (2, 1)]);
+
+        let mut r = renderer();
+        r.draw_shape(&Collider::Circle { center: Point::new(5.0, 5.0), radius: 1.0 }, WHITE).unwrap();
+        assert_eq!(covered(&r), vec![(4, 4), (5, 4), (4, 5), (5, 5)]);
+
+        // a segment is drawn a pixel wide
+        let mut r = renderer();
+        r.draw_shape(&Collider::Capsule { a: Point::new(2.0, 3.5), b: Point::new(6.0, 3.5), radius: 0.0 }, WHITE).unwrap();
+        assert_eq!(covered(&r), vec![(2, 3), (3, 3), (4, 3), (5, 3)]);
+        let mut r = renderer();
+        r.draw_shape(&Collider::Capsule { a: Point::new(2.0, 3.0), b: Point::new(4.0, 3.0), radius: 0.0 }, WHITE).unwrap();
+        assert_eq!(covered(&r), vec![(2, 2), (3, 2), (2, 3), (3, 3)]);
+
+        // anything off screen is cut off
+        let mut r = renderer();
+        r.draw_shape(&Collider::Aabb { min: Point::new(-5.0, 8.0), max: Point::new(1.0, 50.0) }, WHITE).unwrap();
+        assert_eq!(covered(&r), vec![(0, 8), (0, 9)]);
+    }
+
+    #[test]
+    fn shapes_go_through_the_camera() {
+        let mut r = renderer();
+        r.set_camera(View { translation: Vector::new(10.0, 0.0), rotation: 0.0, zoom: 2.0 }).unwrap();
+        r.draw_shape(&Collider::Aabb { min: Point::new(1.0, 1.0), max: Point::new(2.0, 2.0) }, WHITE).unwrap();
+        assert_eq!(covered(&r), vec![(12, 2), (13, 2), (12, 3), (13, 3)]);
+
+        // turned a quarter clockwise around the top left corner
+        let mut r = renderer();
+        r.set_camera(View { translation: Vector::new(10.0, 0.0), rotation: std::f32::consts::FRAC_PI_2, zoom: 1.0 }).unwrap();
+        r.draw_shape(&Collider::Aabb { min: Point::new(0.0, 0.0), max: Point::new(3.0, 1.0) }, WHITE).unwrap();
+        assert_eq!(covered(&r), vec![(9, 0), (9, 1), (9, 2)]);
+    }
+
+    #[test]
+    fn colours_are_blended_by_alpha() {
+        let mut r = renderer();
+        let half_red = Color::new(1.0, 0.0, 0.0, 0.5);
+        let square = Collider::Aabb { min: Point::new(0.0, 0.0), max: Point::new(1.0, 1.0) };
+        r.draw_shape(&square, half_red).unwrap();
+        assert_eq!(r.pixel(0, 0), [128, 0, 0, 255]);
+
+        let mut r = SoftwareRenderer::new(1, 1);
+        r.draw_shape(&square, half_red).unwrap();
+        assert_eq!(r.pixel(0, 0), [128, 0, 0, 128]);
+        assert_eq!(r.pixels().len(), 4);
+    }
+
+    #[test]
+    fn sprites_sample_the_nearest_texel_of_their_frame() {
+        let (e, f, g, h) = ([255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255], [255, 255, 0, 255]);
+        let grey = [50, 50, 50, 255];
+        // two frames side by side, the second is
+        // e f
+        // g h
+        let pixels = [grey, grey, e, f, grey, grey, g, h].concat();
+        let image = ImageData::new(4, 2, pixels);
+        let quad = SpriteQuad {
+            image: ImageHandle(0),
+            source: Rect::new(0.5, 0.0, 0.5, 1.0),
+            position: Point::new(2.0, 2.0),
+            rotation: 0.0,
+            size: Vector::new(4.0, 4.0),
+            pivot: Vector::zeros(),
+            flip_x: false,
+            flip_y: false,
+            tint: WHITE,
+        };
+        let draw = |quad: SpriteQuad| {
+            let mut r = renderer();
+            r.draw_sprite(&quad, &image).unwrap();
+            r
+        };
+
+        let r = draw(quad);
+        assert_eq!((r.pixel(2, 2), r.pixel(3, 3), r.pixel(4, 2), r.pixel(5, 5)), (e, e, f, h));
+        assert_eq!(covered(&r).len(), 16);
+        assert_eq!((r.pixel(1, 2), r.pixel(6, 2)), ([0, 0, 0, 255], [0, 0, 0, 255]));
+
+        let r = draw(SpriteQuad { flip_x: true, ..quad });
+        assert_eq!((r.pixel(2, 2), r.pixel(4, 2)), (f, e));
+        let r = draw(SpriteQuad { flip_y: true, ..quad });
+        assert_eq!((r.pixel(2, 2), r.pixel(2, 4)), (g, e));
+
+        let r = draw(SpriteQuad { tint: Color::new(0.5, 1.0, 1.0, 1.0), ..quad });
+        assert_eq!((r.pixel(2, 2), r.pixel(5, 5)), ([128, 0, 0, 255], [128, 255, 0, 255]));
+
+        // turned a quarter clockwise around its middle, the top left corner ends up top right
+        let turned = SpriteQuad { position: Point::new(10.0, 5.0), rotation: std::f32::consts::FRAC_PI_2, pivot: Vector::new(0.5, 0.5), ..quad };
+        let r = draw(turned);
+        assert_eq!((r.pixel(11, 3), r.pixel(8, 3), r.pixel(8, 6)), (e, g, h));
+        assert!(covered(&r).iter().all(|(x, y)| (8..12).contains(x) && (3..7).contains(y)));
+
+        let mut r = renderer();
+        r.draw_sprite(&quad, &ImageData { width: 0, height: 0, pixels: Vec::new() }).unwrap();
+        assert!(covered(&r).is_empty());
+    }
+
+    #[test]
+    fn text_uses_the_built_in_font() {
+        let mut r = SoftwareRenderer::new(24, 10);
+        r.clear(BLACK);
+        r.draw_text("1-", Point::new(0.0, 0.0), WHITE).unwrap();
+        let lit = covered(&r);
+        // the top of the 1 is in its middle column, scaled up
+        assert!(lit.contains(&(2, 0)) && lit.contains(&(3, 1)));
+        assert!(!lit.contains(&(0, 0)));
+        // the dash starts after the 1 and its space
+        assert!(lit.contains(&(8, 4)) && lit.contains(&(13, 5)));
+        assert!(!lit.contains(&(8, 0)));
+        assert!(lit.iter().all(|(x, y)| *x < 16 && *y < 10));
+        assert_eq!(glyph('a'), glyph('A'));
+        assert_eq!(glyph('~'), glyph('?'));
+    }
+}
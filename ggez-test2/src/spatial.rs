@@ -0,0 +1,264 @@
+use crate::collision::*;
+use crate::components::*;
+use crate::world::*;
+
+/// The first shape a ray runs into
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RayHit {
+    pub entity: Entity,
+    pub point: Point,
+    /// outward normal of the shape where the ray enters it
+    pub normal: Vector,
+    /// distance from the ray's origin to `point`
+    pub distance: f32,
+}
+
+/// Shape queries against every entity with a `Position` and a `Shape`. Candidates come from
+/// `world.spatial_index` and `world.sleeping`, which are up to date at the end of every step.
+impl World {
+    /// Entities the box from `min` to `max` may overlap, in entity order
+    fn query_candidates(&self, min: Point, max: Point) -> Vec<Entity> {
+        let mut entities = Vec::new();
+        let mut found = Vec::new();
+        for index in &[&self.spatial_index, &self.sleeping] {
+            index.query(min, max, &mut found);
+            entities.extend(found.iter().map(|i| index.proxies()[*i].entity));
+        }
+        entities.retain(|e| self.is_live(*e));
+        entities.sort_by_key(|e| (e.index, e.generation));
+        entities.dedup();
+        entities
+    }
+
+    /// The entity's `Rotation` angle and `Anchor`, or their defaults
+    pub fn placement(&self, e: Entity) -> (f32, Anchor) {
+        let angle = self.get::<Rotation>(e).map(|r| r.angle).unwrap_or(0.0);
+        (angle, self.get::<Anchor>(e).copied().unwrap_or_default())
+    }
+
+    /// The entity's shape placed at its position, see `Shape::collider`
+    pub fn collider(&self, e: Entity) -> Option<Collider> {
+        let (angle, anchor) = self.placement(e);
+        match (self.get::<Position>(e), self.get::<Shape>(e)) {
+            (Some(p), Some(s)) => Some(s.collider(p, angle, anchor)),
+            _ => None,
+        }
+    }
+
+    /// Bounds of the entity's shape placed at its position, see `Shape::aabb`
+    pub fn aabb(&self, e: Entity) -> Option<(Point, Point)> {
+        self.collider(e).map(|c| c.bounds())
+    }
+
+    /// Box around every proxy in `spatial_index` and `sleeping`, None if both are empty
+    fn proxy_bounds(&self) -> Option<(Point, Point)> {
+        let mut proxies = self.spatial_index.proxies().iter().chain(self.sleeping.proxies());
+        let first = proxies.next()?;
+        Some(proxies.fold((first.min, first.max), |(min, max), p| {
+            (Point::new(min.x.min(p.min.x), min.y.min(p.min.y)), Point::new(max.x.max(p.max.x), max.y.max(p.max.y)))
+        }))
+    }
+
+    /// The closest shape hit by a ray from `origin` along `dir` within `max_distance`. `dir`
+    /// doesn't have to be normalised and `max_distance` has to be finite. Ties go to the
+    /// entity that comes first.
+    pub fn raycast(&self, origin: Point, dir: Vector, max_distance: f32) -> Option<RayHit> {
+        let length = dir.norm();
+        if length == 0.0 || max_distance < 0.0 || !max_distance.is_finite() {
+            return None;
+        }
+        let dir = dir / length;
+
+        // only the part of the ray inside the box around everything can hit anything, so a
+        // long ray doesn't walk through empty cells
+        let (min, max) = self.proxy_bounds()?;
+        let pad = Vector::new(f32::EPSILON, f32::EPSILON);
+        let (min, max) = (min - pad, max + pad);
+        let (mut start, mut limit) = (0.0f32, max_distance);
+        for axis in 0..2 {
+            if dir[axis] == 0.0 {
+                if origin[axis] < min[axis] || origin[axis] > max[axis] {
+                    return None;
+                }
+            } else {
+                let a = (min[axis] - origin[axis]) / dir[axis];
+                let b = (max[axis] - origin[axis]) / dir[axis];
+                start = start.max(a.min(b));
+                limit = limit.min(a.max(b));
+            }
+        }
+        if start > limit {
+            return None;
+        }
+
+        // walks the ray a cell at a time, so a hit close to the origin doesn't have to look at
+        // everything further along
+        let step = crate::systems::CONTACT_GRID_CELL_SIZE;
+        loop {
+            let end = (start + step).min(limit);
+            let a = origin + dir * start;
+            let b = origin + dir * end;
+            let min = Point::new(a.x.min(b.x), a.y.min(b.y));
+            let max = Point::new(a.x.max(b.x), a.y.max(b.y));
+
+            let mut closest: Option<RayHit> = None;
+            // boxes of horizontal and vertical rays are flat, grow them so the query still
+            // finds what they pass through
+            for e in self.query_candidates(min - pad, max + pad) {
+                let hit = self.collider(e).and_then(|c| c.raycast(origin, dir));
+                if let Some((distance, normal)) = hit {
+                    if distance <= max_distance && closest.map(|c| distance < c.distance).unwrap_or(true) {
+                        closest = Some(RayHit { entity: e, point: origin + dir * distance, normal, distance });
+                    }
+                }
+            }
+            // anything hit further along than this cell may still be beaten by a shape that
+            // only shows up in a later cell
+            if let Some(hit) = closest {
+                if hit.distance <= end {
+                    return Some(hit);
+                }
+            }
+            if end >= limit {
+                return closest;
+            }
+            start = end;
+        }
+    }
+
+    /// Entities whose shape contains `point`, in entity order
+    pub fn point_query(&self, point: Point) -> Vec<Entity> {
+        self.query_candidates(point, point)
+            .into_iter()
+            .filter(|e| self.collider(*e).map(|c| c.contains(point)).unwrap_or(false))
+            .collect()
+    }
+
+    /// Entities overlapping `shape` placed at `position`, each with the contact the shape
+    /// would have with it. Contact normals point from the shape into the entity.
+    pub fn overlap(&self, shape: &Shape, position: &Position) -> Vec<(Entity, Contact)> {
+        let query = Collider::new(position, shape);
+        let (min, max) = query.bounds();
+        self.query_candidates(min, max)
+            .into_iter()
+            .filter_map(|e| self.collider(e).and_then(|c| collide(&query, &c)).map(|contact| (e, contact)))
+            .collect()
+    }
+
+    /// The first shape `e` runs into if it moves by `motion` from its current position, and
+    /// the fraction of `motion` it gets through before touching it, see
+    /// `Collider::time_of_impact`. Only shapes `e` collides with count, so sensors and
+    /// filtered out layers never stop it. Ties go to the entity that comes first.
+    pub fn sweep(&self, e: Entity, motion: Vector) -> Option<(Entity, f32)> {
+        let filter = self.get::<CollisionFilter>(e).copied().unwrap_or_default();
+        let collider = self.collider(e)?;
+        if filter.sensor {
+            return None;
+        }
+
+        let (start_min, start_max) = collider.bounds();
+        let end_min = start_min + motion;
+        let end_max = start_max + motion;
+        let min = Point::new(start_min.x.min(end_min.x), start_min.y.min(end_min.y));
+        let max = Point::new(start_max.x.max(end_max.x), start_max.y.max(end_max.y));
+
+        let mut first: Option<(Entity, f32)> = None;
+        for other in self.query_candidates(min, max) {
+            let other_filter = self.get::<CollisionFilter>(other).copied().unwrap_or_default();
+            if other == e || other_filter.sensor || !filter.interacts(&other_filter) {
+                continue;
+            }
+            if let Some(t) = self.collider(other).and_then(|c| collider.time_of_impact(motion, &c)) {
+                if first.map(|(_, first)| t < first).unwrap_or(true) {
+                    first = Some((other, t));
+                }
+            }
+        }
+        first
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::systems::*;
+    use ggez::graphics::WHITE;
+
+    fn spawn(world: &mut World, x: f32, y: f32, shape_type: ShapeType) -> Entity {
+        let e = world.spawn();
+        world.set(e, Position { x, y });
+        world.set(e, Shape { shape_type, colour: WHITE });
+        e
+    }
+
+    fn world() -> (World, Entity, Entity, Entity) {
+        let mut world = World::new();
+        let circle = spawn(&mut world, 100.0, 100.0, ShapeType::Circle(10.0));
+        let rect = spawn(&mut world, 200.0, 90.0, ShapeType::Rectangle(20.0, 20.0));
+        let sleeper = spawn(&mut world, 300.0, 100.0, ShapeType::Circle(10.0));
+        world.set(sleeper, RigidBody::dynamic(1.0));
+        world.set(sleeper, Sleep { asleep: true, idle_time: 1.0 });
+        SpatialIndexSystem::process(&mut world);
+        (world, circle, rect, sleeper)
+    }
+
+    #[test]
+    fn raycast_finds_the_closest_hit() {
+        let (world, circle, rect, sleeper) = world();
+        let right = Vector::new(1.0, 0.0);
+
+        let hit = world.raycast(Point::new(0.0, 100.0), right, 1000.0).unwrap();
+        assert_eq!(hit.entity, circle);
+        assert_eq!(hit.point, Point::new(90.0, 100.0));
+        assert_eq!(hit.normal, -right);
+        assert_eq!(hit.distance, 90.0);
+
+        // the direction doesn't need to be a unit vector
+        let hit = world.raycast(Point::new(150.0, 100.0), right * 5.0, 1000.0).unwrap();
+        assert_eq!((hit.entity, hit.distance), (rect, 50.0));
+        assert_eq!(world.raycast(Point::new(250.0, 100.0), right, 1000.0).unwrap().entity, sleeper);
+
+        assert_eq!(world.raycast(Point::new(0.0, 100.0), right, 89.0), None);
+        assert_eq!(world.raycast(Point::new(0.0, 100.0), -right, 1000.0), None);
+        assert_eq!(world.raycast(Point::new(0.0, 0.0), right, 1000.0), None);
+    }
+
+    #[test]
+    fn long_rays_stop_where_the_shapes_end() {
+        let (world, circle, _, _) = world();
+        let right = Vector::new(1.0, 0.0);
+
+        // only finite distances, and misses come back without walking the whole ray
+        assert_eq!(world.raycast(Point::new(0.0, 100.0), right, f32::INFINITY), None);
+        assert_eq!(world.raycast(Point::new(0.0, 100.0), right, f32::NAN), None);
+        assert_eq!(world.raycast(Point::new(0.0, 0.0), right, f32::MAX), None);
+        assert_eq!(world.raycast(Point::new(400.0, 100.0), right, f32::MAX), None);
+        assert_eq!(world.raycast(Point::new(0.0, 100.0), right, f32::MAX).unwrap().entity, circle);
+        // from far outside the shapes, as close as f32 gets that far out
+        let hit = world.raycast(Point::new(-1.0e4, 100.0), right, f32::MAX).unwrap();
+        assert_eq!(hit.entity, circle);
+        assert!((hit.point - Point::new(90.0, 100.0)).norm() < 1.0, "{:?}", hit);
+
+        assert_eq!(World::new().raycast(Point::new(0.0, 0.0), right, f32::MAX), None);
+    }
+
+    #[test]
+    fn point_and_overlap_queries() {
+        let (mut world, circle, rect, sleeper) = world();
+
+        assert_eq!(world.point_query(Point::new(105.0, 100.0)), vec![circle]);
+        assert_eq!(world.point_query(Point::new(210.0, 100.0)), vec![rect]);
+        assert_eq!(world.point_query(Point::new(300.0, 100.0)), vec![sleeper]);
+        assert!(world.point_query(Point::new(150.0, 100.0)).is_empty());
+
+        // a box reaching from the circle to the rectangle
+        let shape = Shape { shape_type: ShapeType::Rectangle(100.0, 10.0), colour: WHITE };
+        let hits = world.overlap(&shape, &Position { x: 105.0, y: 95.0 });
+        assert_eq!(hits.iter().map(|(e, _)| *e).collect::<Vec<_>>(), vec![circle, rect]);
+        assert_eq!(hits[1].1.normal, Vector::new(1.0, 0.0));
+
+        // despawned entities drop out straight away, before the index is rebuilt
+        world.despawn(circle);
+        assert!(world.point_query(Point::new(105.0, 100.0)).is_empty());
+    }
+}
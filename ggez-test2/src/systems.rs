@@ -0,0 +1,999 @@
+use crate::broadphase::*;
+use crate::collision::*;
+use crate::components::*;
+use crate::physics::*;
+use crate::world::*;
+
+/// A simulation system. Systems only see the `World`, so they run the same with or without a window.
+pub trait System {
+    fn process(world: &mut World);
+}
+
+/// Cell size of the broadphase grid, a bit larger than a particle
+pub const CONTACT_GRID_CELL_SIZE: f32 = 16.0;
+
+/// Colliders and proxies of every shape that isn't asleep. Sleeping bodies don't move, so they
+/// stay in `world.sleeping`, which is only rebuilt when one falls asleep or wakes up. Sensors are
+/// always collected, they have to keep noticing what is overlapping them.
+fn collect_shapes(world: &mut World) -> (Vec<Collider>, Vec<Proxy>) {
+    let mut sleeping = Vec::new();
+    let mut colliders = Vec::new();
+    let mut proxies = Vec::new();
+    for e in world.query_filtered::<(With<Position>, With<Shape>)>() {
+        let filter = world.get::<CollisionFilter>(e).copied().unwrap_or_default();
+        if is_asleep(world, e) && !filter.sensor {
+            sleeping.push(e);
+            continue;
+        }
+        let collider = world.collider(e).unwrap();
+        let (min, max) = collider.bounds();
+        colliders.push(collider);
+        proxies.push(Proxy { entity: e, min, max, filter });
+    }
+    if !world.sleeping.proxies().iter().map(|p| p.entity).eq(sleeping.iter().copied()) {
+        let sleeping = sleeping.into_iter()
+            .map(|e| {
+                let (min, max) = world.aabb(e).unwrap();
+                let filter = world.get::<CollisionFilter>(e).copied().unwrap_or_default();
+                Proxy { entity: e, min, max, filter }
+            })
+            .collect();
+        world.sleeping.rebuild(sleeping);
+    }
+    (colliders, proxies)
+}
+
+/// Narrowphase for one pair from the broadphase. Overlaps with a sensor are recorded as
+/// (sensor, other), everything else gets a manifold.
+fn narrowphase(
+    (a, ca): (&Proxy, &Collider),
+    (b, cb): (&Proxy, &Collider),
+    contacts: &mut Vec<Manifold>,
+    sensor_contacts: &mut Vec<(Entity, Entity)>,
+) {
+    if a.filter.sensor || b.filter.sensor {
+        if collide(ca, cb).is_some() {
+            if a.filter.sensor {
+                sensor_contacts.push((a.entity, b.entity));
+            }
+            if b.filter.sensor {
+                sensor_contacts.push((b.entity, a.entity));
+            }
+        }
+    } else if let Some(contact) = collide(ca, cb) {
+        contacts.push(Manifold { a: a.entity, b: b.entity, contact });
+    }
+}
+
+fn pair_key((sensor, other): &(Entity, Entity)) -> (usize, u64, usize, u64) {
+    (sensor.index, sensor.generation, other.index, other.generation)
+}
+
+/// Compares this step's sensor overlaps with the last step's, both sorted by `pair_key`
+fn trigger_events(previous: &[(Entity, Entity)], current: &[(Entity, Entity)]) -> Vec<TriggerEvent> {
+    let found = |pairs: &[(Entity, Entity)], pair| pairs.binary_search_by_key(&pair_key(pair), pair_key).is_ok();
+    let mut events: Vec<TriggerEvent> = current.iter()
+        .map(|pair| {
+            let kind = if found(previous, pair) { TriggerEventKind::Stay } else { TriggerEventKind::Enter };
+            TriggerEvent { kind, sensor: pair.0, other: pair.1 }
+        })
+        .chain(previous.iter()
+            .filter(|pair| !found(current, pair))
+            .map(|pair| TriggerEvent { kind: TriggerEventKind::Exit, sensor: pair.0, other: pair.1 }))
+        .collect();
+    events.sort_by_key(|event| pair_key(&(event.sensor, event.other)));
+    events
+}
+
+/// Finds every pair of overlapping shapes where at least one of them is awake and stores a
+/// manifold for each in `world.contacts`. Overlaps with sensors go to `world.sensor_contacts`
+/// instead, and `world.trigger_events` says how they changed since the last step.
+pub struct ContactSystem;
+impl System for ContactSystem {
+    fn process(world: &mut World) {
+        let (colliders, proxies) = collect_shapes(world);
+
+        // pairs where neither body moves can't change, so they aren't tested, unless a sensor
+        // has to report them
+        let awake: Vec<bool> = proxies.iter().map(|p| is_awake(world, p.entity) || p.filter.sensor).collect();
+
+        let mut contacts = Vec::new();
+        let mut sensor_contacts = Vec::new();
+        for (i, j) in world.broadphase.pairs(&proxies) {
+            if !awake[i] && !awake[j] {
+                continue;
+            }
+            narrowphase((&proxies[i], &colliders[i]), (&proxies[j], &colliders[j]), &mut contacts, &mut sensor_contacts);
+        }
+
+        let mut found = Vec::new();
+        for (i, proxy) in proxies.iter().enumerate() {
+            if !awake[i] {
+                continue;
+            }
+            world.sleeping.query(proxy.min, proxy.max, &mut found);
+            for sleeper in found.iter().map(|k| &world.sleeping.proxies()[*k]) {
+                if !proxy.filter.interacts(&sleeper.filter) {
+                    continue;
+                }
+                let collider = world.collider(sleeper.entity).unwrap();
+                narrowphase((proxy, &colliders[i]), (sleeper, &collider), &mut contacts, &mut sensor_contacts);
+            }
+        }
+        world.contacts = contacts;
+
+        sensor_contacts.sort_by_key(pair_key);
+        world.trigger_events = trigger_events(&world.sensor_contacts, &sensor_contacts);
+        world.sensor_contacts = sensor_contacts;
+    }
+}
+
+/// Indexes where every shape ended up this step, for the queries in `spatial`. Runs last, after
+/// commands are applied, so the queries see the same world as everything outside the step.
+pub struct SpatialIndexSystem;
+impl System for SpatialIndexSystem {
+    fn process(world: &mut World) {
+        let (_, proxies) = collect_shapes(world);
+        world.spatial_index.rebuild(proxies);
+    }
+}
+
+/// Moves the camera after its target and wears off its shake. A camera whose target was
+/// despawned stops where it is.
+pub struct CameraSystem;
+impl System for CameraSystem {
+    fn process(world: &mut World) {
+        if let Some(e) = world.camera.target {
+            if !world.is_live(e) {
+                world.camera.target = None;
+            }
+        }
+        let target = world.camera.target.and_then(|e| world.get::<Position>(e)).map(|p| Point::new(p.x, p.y));
+        let (screen, dt) = (world.screen, world.time.dt);
+        world.camera.update(target, screen, dt);
+    }
+}
+
+/// Resolves the contacts found by `ContactSystem` together with the joints, and keeps
+/// entities inside the screen
+pub struct CollissionSystem;
+impl System for CollissionSystem {
+    fn process(world: &mut World) {
+        let contacts = std::mem::take(&mut world.contacts);
+        for manifold in &contacts {
+            wake_on_impact(world, manifold.a, manifold.b);
+            wake_on_impact(world, manifold.b, manifold.a);
+        }
+
+        let mut joints = Vec::new();
+        for e in world.query_filtered::<With<Joint>>().collect::<Vec<_>>() {
+            let joint = *world.get::<Joint>(e).unwrap();
+            if !world.is_live(joint.a) || !world.is_live(joint.b) {
+                world.commands.despawn(e);
+                continue;
+            }
+            wake_on_impact(world, joint.a, joint.b);
+            wake_on_impact(world, joint.b, joint.a);
+            joints.push(joint);
+        }
+        let dt = world.time.dt;
+        for joint in &joints {
+            spring_impulse(world, joint, dt);
+        }
+
+        // the screen edges act as fixed walls for dynamic bodies that are awake
+        let screen_rect = world.screen;
+        let edge_material = PhysicsMaterial::default();
+        let dynamic: Vec<Entity> = world.query_filtered::<(With<Position>, With<Velocity>)>()
+            .filter(|e| world.has::<Shape>(*e) && world.get::<RigidBody>(*e).map(|rb| rb.is_dynamic()).unwrap_or(false) && !is_asleep(world, *e))
+            .collect();
+        let mut edge_contacts = Vec::new();
+        for e in &dynamic {
+            let (min, max) = world.aabb(*e).unwrap();
+            // check if we hit the bottom or the top of the screen
+            if max.y >= screen_rect.bottom() {
+                edge_contacts.push((*e, Vector::new(0.0, 1.0), max.y - screen_rect.bottom()));
+            } else if min.y <= screen_rect.top() {
+                edge_contacts.push((*e, Vector::new(0.0, -1.0), screen_rect.top() - min.y));
+            }
+            // check if we hit the edge of the screen
+            if max.x >= screen_rect.right() {
+                edge_contacts.push((*e, Vector::new(1.0, 0.0), max.x - screen_rect.right()));
+            } else if min.x <= screen_rect.left() {
+                edge_contacts.push((*e, Vector::new(-1.0, 0.0), screen_rect.left() - min.x));
+            }
+        }
+
+        // velocities first, repeated so piles and chains can settle, then positions once
+        for _ in 0..world.physics.solver_iterations {
+            for (e, normal, _) in &edge_contacts {
+                fixed_contact_impulse(world, *e, *normal, edge_material);
+            }
+            for manifold in &contacts {
+                contact_impulse(world, manifold);
+            }
+            for joint in &joints {
+                joint_impulse(world, joint);
+            }
+        }
+        for manifold in &contacts {
+            correct_contact(world, manifold);
+        }
+        for joint in &joints {
+            correct_joint(world, joint);
+        }
+        for (e, normal, depth) in &edge_contacts {
+            correct_fixed_contact(world, *e, *normal, *depth);
+        }
+        world.contacts = contacts;
+
+        for e in dynamic {
+            // positional correction only removes part of the overlap, and contacts with other
+            // bodies may have pushed it further out
+            let clamped = clamp_to_screen(world, e, *world.get::<Position>(e).unwrap());
+            *world.get_mut::<Position>(e).unwrap() = clamped;
+        }
+    }
+}
+
+/// Wakes `sleeper` if it is asleep and `other` runs into it fast enough. Slower bodies and
+/// bodies that are settling down just come to rest on it, which lets stacks fall asleep.
+fn wake_on_impact(world: &mut World, sleeper: Entity, other: Entity) {
+    if !is_asleep(world, sleeper) || !is_awake(world, other) {
+        return;
+    }
+    let settling = world.get::<Sleep>(other).map(|s| s.idle_time > 0.0).unwrap_or(false);
+    let speed = world.get::<Velocity>(other).map(|v| Vector::new(v.xv, v.yv).norm()).unwrap_or(0.0);
+    if !settling && speed >= world.physics.sleep_speed {
+        world.get_mut::<Sleep>(sleeper).unwrap().wake();
+    }
+}
+
+/// Puts dynamic bodies with a `Sleep` component to sleep once they have been still for long
+/// enough, and despawns long sleepers if `PhysicsSettings::despawn_after_sleeping` is set
+pub struct SleepSystem;
+impl System for SleepSystem {
+    fn process(world: &mut World) {
+        let settings = world.physics;
+        let dt = world.time.dt;
+
+        let bodies: Vec<Entity> = world.query_filtered::<(With<Sleep>, With<Velocity>)>()
+            .filter(|e| world.get::<RigidBody>(*e).map(|rb| rb.is_dynamic()).unwrap_or(false))
+            .collect();
+        for e in bodies {
+            let v = *world.get::<Velocity>(e).unwrap();
+            // in a stack gravity and the contacts keep fighting over the velocity while the body
+            // stays put, so a body counts as still if either it or its velocity is
+            let mut speed = Vector::new(v.xv, v.yv).norm();
+            if let (Some(p), Some(previous)) = (world.get::<Position>(e), world.get::<PreviousPosition>(e)) {
+                speed = speed.min(Vector::new(p.x - previous.x, p.y - previous.y).norm() / dt);
+            }
+
+            let sleep = world.get_mut::<Sleep>(e).unwrap();
+            if sleep.asleep || speed < settings.sleep_speed {
+                sleep.idle_time += dt;
+            } else {
+                sleep.idle_time = 0.0;
+            }
+            if !sleep.asleep && sleep.idle_time >= settings.time_to_sleep {
+                sleep.asleep = true;
+                *world.get_mut::<Velocity>(e).unwrap() = Velocity::default();
+                // sleepers are left out of the screen edge checks, so they must not doze off
+                // halfway out of the screen where a contact pushed them
+                if let Some(p) = world.get::<Position>(e) {
+                    let clamped = clamp_to_screen(world, e, *p);
+                    *world.get_mut::<Position>(e).unwrap() = clamped;
+                }
+            }
+
+            if let Some(limit) = settings.despawn_after_sleeping {
+                let sleep = world.get::<Sleep>(e).unwrap();
+                if sleep.asleep && sleep.idle_time - settings.time_to_sleep >= limit {
+                    world.commands.despawn(e);
+                }
+            }
+        }
+    }
+}
+
+/// Moves `position` just far enough that the bounds of the entity's shape placed there end up
+/// inside the screen
+fn clamp_to_screen(world: &World, e: Entity, position: Position) -> Position {
+    let screen = world.screen;
+    let shape = match world.get::<Shape>(e) {
+        Some(shape) => shape,
+        None => return position,
+    };
+    let (angle, anchor) = world.placement(e);
+    let (min, max) = shape.aabb(&position, angle, anchor);
+    Position {
+        x: position.x + (screen.left() - min.x).max(0.0) + (screen.right() - max.x).min(0.0),
+        y: position.y + (screen.top() - min.y).max(0.0) + (screen.bottom() - max.y).min(0.0),
+    }
+}
+
+/// Where a `Ccd` body moving from `from` to `to` should stop. That is where it first touches
+/// something, plus `PENETRATION_SLOP` further so the contact is picked up this step without
+/// being pushed apart. Otherwise the body would stop just short of it every step.
+fn sweep_to_first_hit(world: &World, e: Entity, from: Position, to: Position) -> Position {
+    let motion = Vector::new(to.x - from.x, to.y - from.y);
+    match world.sweep(e, motion) {
+        Some((_, t)) => {
+            let length = motion.norm();
+            let travelled = motion * ((t * length + PENETRATION_SLOP).min(length) / length);
+            Position { x: from.x + travelled.x, y: from.y + travelled.y }
+        },
+        None => to,
+    }
+}
+
+/// Integrates forces and gravity into velocities and velocities into positions, over the
+/// fixed step `world.time.dt`. `Ccd` bodies are swept to their new position.
+pub struct MovementSystem;
+impl System for MovementSystem {
+    fn process(world: &mut World) {
+        let settings = world.physics;
+        let dt = world.time.dt;
+
+        // applying a force wakes a body up
+        for e in world.query_filtered::<(With<Force>, With<Sleep>)>().collect::<Vec<_>>() {
+            if *world.get::<Force>(e).unwrap() != Force::default() {
+                world.get_mut::<Sleep>(e).unwrap().wake();
+            }
+        }
+
+        let bodies: Vec<Entity> = world.query_filtered::<(With<Position>, With<Velocity>)>()
+            .filter(|e| world.has::<Shape>(*e) && world.get::<RigidBody>(*e).map(|rb| rb.is_moving()).unwrap_or(false))
+            .collect();
+        for e in bodies {
+            let p = *world.get::<Position>(e).unwrap();
+            let v = *world.get::<Velocity>(e).unwrap();
+            let rb = *world.get::<RigidBody>(e).unwrap();
+            // goes through `set` so hooks and observers see the first one being added
+            world.set(e, PreviousPosition { x: p.x, y: p.y });
+            if is_asleep(world, e) {
+                continue;
+            }
+
+            if rb.body_type == BodyType::Kinematic {
+                // kinematic bodies go wherever their velocity takes them
+                *world.get_mut::<Position>(e).unwrap() = Position { x: p.x + v.xv * dt, y: p.y + v.yv * dt };
+                continue;
+            }
+
+            let force = world.get::<Force>(e).map(|f| Vector::new(f.fx, f.fy)).unwrap_or_else(Vector::zeros);
+            let acceleration = settings.gravity + force * rb.inverse_mass();
+            let (position, velocity) = integrate(settings.integrator, Vector::new(p.x, p.y), Vector::new(v.xv, v.yv), acceleration, dt);
+            let velocity = settings.limit_velocity(velocity, dt);
+
+            let mut clamped = clamp_to_screen(world, e, Position { x: position.x, y: position.y });
+            if world.has::<Ccd>(e) {
+                clamped = sweep_to_first_hit(world, e, p, clamped);
+            }
+            *world.get_mut::<Position>(e).unwrap() = clamped;
+            *world.get_mut::<Velocity>(e).unwrap() = Velocity { xv: velocity.x, yv: velocity.y };
+        }
+
+        // forces only act for a single step
+        for e in world.query_filtered::<With<Force>>().collect::<Vec<_>>() {
+            *world.get_mut::<Force>(e).unwrap() = Force::default();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use ggez::graphics::{Rect, WHITE};
+
+    /// 64Hz keeps every step exact in binary
+    fn world() -> World {
+        let mut world = World::new();
+        world.screen = Rect::new(0.0, 0.0, 400.0, 400.0);
+        world.time.dt = 1.0 / 64.0;
+        world
+    }
+
+    fn step(world: &mut World) {
+        MovementSystem::process(world);
+        ContactSystem::process(world);
+        CollissionSystem::process(world);
+        SleepSystem::process(world);
+        world.apply_commands();
+    }
+
+    #[test]
+    fn particles_bounce_off_obstacles() {
+        let mut world = world();
+        let obstacle = world.spawn();
+        world.set(obstacle, Position { x: 100.0, y: 100.0 });
+        world.set(obstacle, Shape { shape_type: ShapeType::Rectangle(20.0, 20.0), colour: WHITE });
+        let particle = world.spawn();
+        world.set(particle, Position { x: 80.0, y: 110.0 });
+        world.set(particle, Velocity { xv: 320.0, yv: 0.0 });
+        world.set(particle, Shape { shape_type: ShapeType::Circle(4.0), colour: WHITE });
+        world.set(particle, RigidBody::dynamic(1.0));
+
+        let mut hit = false;
+        for _ in 0..10 {
+            step(&mut world);
+            hit |= !world.contacts.is_empty();
+
+            // positional correction may leave a little overlap, but never lets the centre in
+            assert!(world.get::<Position>(particle).unwrap().x < 100.0);
+        }
+        assert!(hit);
+        assert!(world.get::<Position>(particle).unwrap().x <= 96.0);
+        assert!(world.get::<Velocity>(particle).unwrap().xv < 0.0);
+    }
+
+    #[test]
+    fn particles_bounce_off_each_other() {
+        let mut world = world();
+        let spawn = |world: &mut World, x: f32, xv: f32| {
+            let e = world.spawn();
+            world.set(e, Position { x, y: 200.0 });
+            world.set(e, Velocity { xv, yv: 0.0 });
+            world.set(e, Shape { shape_type: ShapeType::Circle(4.0), colour: WHITE });
+            world.set(e, PhysicsMaterial { restitution: 1.0, friction: 0.0, density: 1.0 });
+            world.set(e, RigidBody::dynamic(1.0));
+            e
+        };
+        let a = spawn(&mut world, 100.0, 128.0);
+        let b = spawn(&mut world, 110.0, -128.0);
+
+        for _ in 0..4 {
+            step(&mut world);
+        }
+
+        assert!(world.get::<Velocity>(a).unwrap().xv < 0.0);
+        assert!(world.get::<Velocity>(b).unwrap().xv > 0.0);
+        assert!(world.get::<Position>(a).unwrap().x < world.get::<Position>(b).unwrap().x);
+    }
+
+    #[test]
+    fn filtered_bodies_pass_through_each_other() {
+        let mut world = world();
+        let spawn = |world: &mut World, x: f32, xv: f32, filter: CollisionFilter| {
+            let e = world.spawn();
+            world.set(e, Position { x, y: 200.0 });
+            world.set(e, Velocity { xv, yv: 0.0 });
+            world.set(e, Shape { shape_type: ShapeType::Circle(4.0), colour: WHITE });
+            world.set(e, RigidBody::kinematic());
+            world.set(e, filter);
+            e
+        };
+        let a = spawn(&mut world, 100.0, 128.0, CollisionFilter::new(0b01, 0b01));
+        let b = spawn(&mut world, 110.0, -128.0, CollisionFilter::new(0b10, 0b11));
+        let target = world.spawn();
+        world.set(target, Position { x: 110.0, y: 200.0 });
+        world.set(target, Shape { shape_type: ShapeType::Circle(4.0), colour: WHITE });
+        world.set(target, RigidBody::fixed());
+
+        let mut target_hits = 0;
+        for _ in 0..8 {
+            step(&mut world);
+            // b is on a layer a doesn't collide with, but both hit the target on the default layer
+            assert!(!world.contacts.iter().any(|m| (m.a, m.b) == (a, b) || (m.a, m.b) == (b, a)));
+            target_hits += world.contacts.iter().filter(|m| m.a == target || m.b == target).count();
+        }
+        assert!(target_hits > 0);
+        assert!(world.get::<Position>(a).unwrap().x > world.get::<Position>(b).unwrap().x);
+    }
+
+    #[test]
+    fn the_camera_follows_its_target_until_it_is_despawned() {
+        let mut world = world();
+        let ball = spawn_ball(&mut world, 300.0, 100.0, RigidBody::kinematic());
+        world.set(ball, Velocity { xv: 64.0, yv: 0.0 });
+        world.camera.follow_speed = f32::INFINITY;
+        world.camera.follow(ball);
+
+        step(&mut world);
+        CameraSystem::process(&mut world);
+        assert_eq!(world.camera.position, Vector::new(101.0, -100.0));
+        world.time.alpha = 1.0;
+        assert_eq!(world.world_to_screen(Point::new(301.0, 100.0)), Point::new(200.0, 200.0));
+
+        world.despawn(ball);
+        step(&mut world);
+        CameraSystem::process(&mut world);
+        assert_eq!(world.camera.target, None);
+        assert_eq!(world.camera.position, Vector::new(101.0, -100.0));
+    }
+
+    #[test]
+    fn ccd_stops_fast_bodies_at_thin_walls() {
+        let run = |ccd: bool| {
+            let mut world = world();
+            world.physics.gravity = Vector::zeros();
+            let wall = world.spawn();
+            world.set(wall, Position { x: 200.0, y: 0.0 });
+            world.set(wall, Shape { shape_type: ShapeType::Rectangle(2.0, 400.0), colour: WHITE });
+            world.set(wall, RigidBody::fixed());
+            let ball = spawn_ball(&mut world, 100.0, 200.0, RigidBody::dynamic(1.0));
+            world.set(ball, Shape { shape_type: ShapeType::Circle(2.0), colour: WHITE });
+            world.set(ball, Velocity { xv: 1000.0, yv: 0.0 });
+            if ccd {
+                world.set(ball, Ccd);
+            }
+            SpatialIndexSystem::process(&mut world);
+            let mut furthest = 0.0f32;
+            for _ in 0..32 {
+                step(&mut world);
+                furthest = furthest.max(world.get::<Position>(ball).unwrap().x);
+            }
+            furthest
+        };
+
+        // a step of 15.6px carries the ball from one side of the wall to the other
+        assert!(run(false) > 202.0);
+        let furthest = run(true);
+        assert!(furthest < 200.0, "passed the wall at {}", furthest);
+    }
+
+    #[test]
+    fn sensors_report_overlaps_without_pushing() {
+        let mut world = world();
+        let sensor = world.spawn();
+        world.set(sensor, Position { x: 100.0, y: 190.0 });
+        world.set(sensor, Shape { shape_type: ShapeType::Rectangle(20.0, 20.0), colour: WHITE });
+        world.set(sensor, CollisionFilter::sensor(CollisionFilter::DEFAULT_LAYER, CollisionFilter::ALL_LAYERS));
+        let body = world.spawn();
+        world.set(body, Position { x: 90.0, y: 200.0 });
+        world.set(body, Velocity { xv: 64.0, yv: 0.0 });
+        world.set(body, Shape { shape_type: ShapeType::Circle(4.0), colour: WHITE });
+        world.set(body, RigidBody::kinematic());
+
+        let mut kinds = Vec::new();
+        for _ in 0..40 {
+            step(&mut world);
+            assert!(world.contacts.is_empty());
+            for event in &world.trigger_events {
+                assert_eq!((event.sensor, event.other), (sensor, body));
+                if kinds.last() != Some(&event.kind) {
+                    kinds.push(event.kind);
+                }
+            }
+        }
+
+        assert_eq!(kinds, vec![TriggerEventKind::Enter, TriggerEventKind::Stay, TriggerEventKind::Exit]);
+        assert_eq!(world.get::<Velocity>(body).unwrap().xv, 64.0);
+
+        // despawning either side ends the overlap too
+        world.set(body, Position { x: 110.0, y: 200.0 });
+        step(&mut world);
+        assert_eq!(world.trigger_events[0].kind, TriggerEventKind::Enter);
+        world.despawn(body);
+        step(&mut world);
+        assert_eq!(world.trigger_events, vec![TriggerEvent { kind: TriggerEventKind::Exit, sensor, other: body }]);
+    }
+
+    #[test]
+    fn bodies_slide_down_tilted_segments() {
+        let mut world = world();
+        let ramp = world.spawn();
+        world.set(ramp, Position { x: 200.0, y: 200.0 });
+        // clockwise on screen, so the right end is lower
+        world.set(ramp, Rotation { angle: 0.3 });
+        world.set(ramp, Shape { shape_type: ShapeType::Segment(300.0), colour: WHITE });
+        world.set(ramp, RigidBody::fixed());
+        let ball = world.spawn();
+        world.set(ball, Position { x: 200.0, y: 180.0 });
+        world.set(ball, Velocity::default());
+        world.set(ball, Shape { shape_type: ShapeType::Circle(4.0), colour: WHITE });
+        world.set(ball, RigidBody::dynamic(1.0));
+
+        for _ in 0..64 {
+            step(&mut world);
+            // stays on top of the ramp, never sinking through it
+            let p = world.get::<Position>(ball).unwrap();
+            let (sin, cos) = 0.3f32.sin_cos();
+            let above = (p.x - 200.0) * sin - (p.y - 200.0) * cos;
+            assert!(above > 0.0, "{:?} fell through the ramp", p);
+        }
+        assert!(world.get::<Position>(ball).unwrap().x > 210.0);
+    }
+
+    #[test]
+    fn stacks_come_to_rest() {
+        let mut world = world();
+        let stack: Vec<Entity> = (0..6).map(|i| {
+            let e = world.spawn();
+            world.set(e, Position { x: 200.0, y: 396.0 - i as f32 * 8.0 });
+            world.set(e, Velocity::default());
+            world.set(e, Shape { shape_type: ShapeType::Circle(4.0), colour: WHITE });
+            world.set(e, RigidBody::dynamic(1.0));
+            e
+        }).collect();
+
+        for _ in 0..128 {
+            step(&mut world);
+        }
+
+        // the solver iterations carry the support of the floor up to the top of the stack
+        for e in stack {
+            let v = world.get::<Velocity>(e).unwrap();
+            assert!(v.yv.abs() < 1.0, "{:?}", v);
+        }
+    }
+
+    #[test]
+    fn kinematic_bodies_push_dynamic_ones() {
+        let mut world = world();
+        let paddle = world.spawn();
+        world.set(paddle, Position { x: 100.0, y: 380.0 });
+        world.set(paddle, Velocity { xv: 128.0, yv: 0.0 });
+        world.set(paddle, Shape { shape_type: ShapeType::Rectangle(20.0, 20.0), colour: WHITE });
+        world.set(paddle, RigidBody::kinematic());
+        let ball = world.spawn();
+        world.set(ball, Position { x: 130.0, y: 396.0 });
+        world.set(ball, Velocity { xv: 0.0, yv: 0.0 });
+        world.set(ball, Shape { shape_type: ShapeType::Circle(4.0), colour: WHITE });
+        world.set(ball, RigidBody::dynamic(1.0));
+        let wall = world.spawn();
+        world.set(wall, Position { x: 300.0, y: 0.0 });
+        world.set(wall, Velocity { xv: 0.0, yv: 0.0 });
+        world.set(wall, Shape { shape_type: ShapeType::Rectangle(10.0, 400.0), colour: WHITE });
+        world.set(wall, RigidBody::fixed());
+
+        for _ in 0..20 {
+            step(&mut world);
+        }
+
+        // the paddle keeps its course, ignoring gravity and the ball, and the ball is pushed along
+        assert_eq!(*world.get::<Position>(paddle).unwrap(), Position { x: 140.0, y: 380.0 });
+        assert_eq!(*world.get::<Velocity>(paddle).unwrap(), Velocity { xv: 128.0, yv: 0.0 });
+        assert!(world.get::<Position>(ball).unwrap().x > 140.0);
+        assert!(world.get::<Velocity>(ball).unwrap().xv > 0.0);
+        // static bodies stay put even with a velocity
+        assert_eq!(*world.get::<Position>(wall).unwrap(), Position { x: 300.0, y: 0.0 });
+    }
+
+    #[test]
+    fn gravity_and_forces_move_dynamic_bodies() {
+        let mut world = world();
+        world.physics.gravity = Vector::new(0.0, 64.0);
+        let e = world.spawn();
+        world.set(e, Position { x: 100.0, y: 100.0 });
+        world.set(e, Velocity::default());
+        world.set(e, Shape { shape_type: ShapeType::Circle(4.0), colour: WHITE });
+        world.set(e, RigidBody::dynamic(2.0));
+        world.set(e, Force { fx: 256.0, fy: 0.0 });
+
+        MovementSystem::process(&mut world);
+        assert_eq!(*world.get::<Velocity>(e).unwrap(), Velocity { xv: 2.0, yv: 1.0 });
+        assert_eq!(*world.get::<Force>(e).unwrap(), Force::default());
+
+        // the force is gone, gravity keeps pulling
+        MovementSystem::process(&mut world);
+        assert_eq!(*world.get::<Velocity>(e).unwrap(), Velocity { xv: 2.0, yv: 2.0 });
+    }
+
+    #[test]
+    fn movement_fires_hooks_for_previous_position() {
+        let mut world = world();
+        let e = world.spawn();
+        world.set(e, Position { x: 100.0, y: 100.0 });
+        world.set(e, Velocity::default());
+        world.set(e, Shape { shape_type: ShapeType::Circle(4.0), colour: WHITE });
+        world.set(e, RigidBody::dynamic(1.0));
+
+        let added = std::rc::Rc::new(std::cell::Cell::new(0));
+        let counter = added.clone();
+        world.on_add::<PreviousPosition>(move |_, _| counter.set(counter.get() + 1));
+
+        MovementSystem::process(&mut world);
+        MovementSystem::process(&mut world);
+        assert_eq!(added.get(), 1);
+        assert_eq!(*world.get::<PreviousPosition>(e).unwrap(), PreviousPosition { x: 100.0, y: 100.0 + 540.0 / 64.0 / 64.0 });
+    }
+
+    #[test]
+    fn screen_edges_use_the_collider_bounds() {
+        let mut world = world();
+        world.physics.gravity = Vector::zeros();
+        let circle = world.spawn();
+        world.set(circle, Position { x: 2.0, y: 2.0 });
+        world.set(circle, Velocity::default());
+        world.set(circle, Shape { shape_type: ShapeType::Circle(4.0), colour: WHITE });
+        world.set(circle, RigidBody::dynamic(1.0));
+        let rect = world.spawn();
+        world.set(rect, Position { x: 395.0, y: 395.0 });
+        world.set(rect, Velocity::default());
+        world.set(rect, Shape { shape_type: ShapeType::Rectangle(10.0, 10.0), colour: WHITE });
+        world.set(rect, RigidBody::dynamic(1.0));
+
+        MovementSystem::process(&mut world);
+
+        // circles are kept in by their radius around the centre, rectangles by their far corner
+        assert_eq!(*world.get::<Position>(circle).unwrap(), Position { x: 4.0, y: 4.0 });
+        assert_eq!(*world.get::<Position>(rect).unwrap(), Position { x: 390.0, y: 390.0 });
+    }
+
+    #[test]
+    fn every_shape_is_kept_inside_the_screen() {
+        let mut world = world();
+        let triangle = ConvexPolygon::new(&[(0.0, 0.0), (20.0, 0.0), (0.0, 20.0)]).unwrap();
+        let shapes = [
+            ShapeType::Rectangle(20.0, 10.0),
+            ShapeType::Circle(5.0),
+            ShapeType::Polygon(triangle),
+            ShapeType::Capsule(20.0, 5.0),
+            ShapeType::Segment(20.0),
+        ];
+        let mut bodies = Vec::new();
+        for shape_type in shapes.iter() {
+            for anchor in [Anchor::Origin, Anchor::TOP_LEFT, Anchor::CENTER, Anchor::Bounds(1.0, 1.0)] {
+                for (x, xv) in [(2.0, -400.0), (398.0, 400.0)] {
+                    let e = world.spawn();
+                    world.set(e, Position { x, y: 398.0 });
+                    world.set(e, Velocity { xv, yv: 400.0 });
+                    world.set(e, Shape { shape_type: *shape_type, colour: WHITE });
+                    world.set(e, Rotation { angle: 0.5 });
+                    world.set(e, anchor);
+                    world.set(e, RigidBody::dynamic(1.0));
+                    world.set(e, CollisionFilter::new(CollisionFilter::DEFAULT_LAYER, 0));
+                    bodies.push(e);
+                }
+            }
+        }
+
+        for _ in 0..8 {
+            step(&mut world);
+            for e in &bodies {
+                let (min, max) = world.aabb(*e).unwrap();
+                assert!(
+                    min.x >= -1e-3 && min.y >= -1e-3 && max.x <= 400.001 && max.y <= 400.001,
+                    "{:?} anchored at {:?} left the screen: {} {}", world.get::<Shape>(*e).unwrap().shape_type, world.get::<Anchor>(*e).unwrap(), min, max
+                );
+            }
+        }
+    }
+
+    fn spawn_ball(world: &mut World, x: f32, y: f32, body: RigidBody) -> Entity {
+        let e = world.spawn();
+        world.set(e, Position { x, y });
+        world.set(e, Velocity::default());
+        world.set(e, Shape { shape_type: ShapeType::Circle(4.0), colour: WHITE });
+        world.set(e, body);
+        e
+    }
+
+    fn spawn_joint(world: &mut World, joint: Joint) -> Entity {
+        let e = world.spawn();
+        world.set(e, joint);
+        e
+    }
+
+    fn distance(world: &World, a: Entity, b: Entity) -> f32 {
+        let (a, b) = (world.get::<Position>(a).unwrap(), world.get::<Position>(b).unwrap());
+        Vector::new(b.x - a.x, b.y - a.y).norm()
+    }
+
+    #[test]
+    fn ropes_only_pull() {
+        let mut world = world();
+        let hook = spawn_ball(&mut world, 200.0, 100.0, RigidBody::fixed());
+        let ball = spawn_ball(&mut world, 230.0, 100.0, RigidBody::dynamic(1.0));
+        spawn_joint(&mut world, Joint::new(hook, ball, JointKind::Distance { length: 60.0, rope: true }));
+
+        // slack, so the ball falls freely at first
+        step(&mut world);
+        assert!(world.get::<Velocity>(ball).unwrap().yv > 0.0);
+        assert_eq!(world.get::<Position>(ball).unwrap().x, 230.0);
+
+        let mut lowest = 0.0f32;
+        for _ in 0..128 {
+            step(&mut world);
+            assert!(distance(&world, hook, ball) < 61.0, "the rope stretched to {}", distance(&world, hook, ball));
+            lowest = lowest.max(world.get::<Position>(ball).unwrap().y);
+        }
+        // swung down to hang below the hook
+        assert!(lowest > 158.0);
+    }
+
+    #[test]
+    fn rods_keep_their_length_both_ways() {
+        let mut world = world();
+        world.physics.gravity = Vector::zeros();
+        let a = spawn_ball(&mut world, 100.0, 200.0, RigidBody::dynamic(1.0));
+        let b = spawn_ball(&mut world, 150.0, 200.0, RigidBody::dynamic(1.0));
+        world.set(b, Velocity { xv: -100.0, yv: 60.0 });
+        spawn_joint(&mut world, Joint::new(a, b, JointKind::Distance { length: 50.0, rope: false }));
+
+        for _ in 0..64 {
+            step(&mut world);
+            assert!((distance(&world, a, b) - 50.0).abs() < 1.0, "the rod is {} long", distance(&world, a, b));
+        }
+    }
+
+    #[test]
+    fn springs_settle_at_their_rest_length() {
+        let mut world = world();
+        world.physics.gravity = Vector::zeros();
+        let a = spawn_ball(&mut world, 100.0, 200.0, RigidBody::dynamic(1.0));
+        let b = spawn_ball(&mut world, 200.0, 200.0, RigidBody::dynamic(1.0));
+        spawn_joint(&mut world, Joint::new(a, b, JointKind::Spring { rest_length: 50.0, stiffness: 20.0, damping: 2.0 }));
+
+        step(&mut world);
+        assert!(world.get::<Velocity>(a).unwrap().xv > 0.0);
+        assert!(world.get::<Velocity>(b).unwrap().xv < 0.0);
+        for _ in 0..512 {
+            step(&mut world);
+        }
+        assert!((distance(&world, a, b) - 50.0).abs() < 1.0, "the spring is {} long", distance(&world, a, b));
+    }
+
+    #[test]
+    fn pins_hold_the_anchors_together() {
+        let mut world = world();
+        let pivot = spawn_ball(&mut world, 200.0, 100.0, RigidBody::fixed());
+        let bar = spawn_ball(&mut world, 220.0, 100.0, RigidBody::dynamic(1.0));
+        let mut pin = Joint::new(pivot, bar, JointKind::Pin);
+        pin.anchor_b = Vertex { x: -20.0, y: 0.0 };
+        spawn_joint(&mut world, pin);
+
+        for _ in 0..64 {
+            step(&mut world);
+            let p = world.get::<Position>(bar).unwrap();
+            assert!((Vector::new(p.x - 20.0 - 200.0, p.y - 100.0)).norm() < 0.5, "{:?} came off the pin", p);
+        }
+    }
+
+    #[test]
+    fn joints_go_with_their_bodies() {
+        let mut world = world();
+        let a = spawn_ball(&mut world, 100.0, 200.0, RigidBody::dynamic(1.0));
+        let b = spawn_ball(&mut world, 150.0, 200.0, RigidBody::dynamic(1.0));
+        let joint = spawn_joint(&mut world, Joint::new(a, b, JointKind::Pin));
+
+        world.despawn(b);
+        step(&mut world);
+        assert!(!world.is_live(joint));
+    }
+
+    #[test]
+    fn joints_survive_remapping_entities() {
+        let mut original = world();
+        let hook = spawn_ball(&mut original, 200.0, 100.0, RigidBody::fixed());
+        let ball = spawn_ball(&mut original, 260.0, 100.0, RigidBody::dynamic(1.0));
+        spawn_joint(&mut original, Joint::new(hook, ball, JointKind::Distance { length: 60.0, rope: true }));
+
+        // loads the entities into a world where the same handles are taken by something else
+        let mut loaded = world();
+        for _ in 0..3 {
+            loaded.spawn();
+        }
+        let mut map = HashMap::new();
+        for e in original.live_entities().collect::<Vec<_>>() {
+            let copy = loaded.spawn();
+            map.insert(e, copy);
+            if let Some(p) = original.get::<Position>(e) {
+                loaded.set(copy, *p);
+                loaded.set(copy, *original.get::<Velocity>(e).unwrap());
+                loaded.set(copy, *original.get::<Shape>(e).unwrap());
+                loaded.set(copy, *original.get::<RigidBody>(e).unwrap());
+            }
+            if let Some(joint) = original.get::<Joint>(e) {
+                loaded.set(copy, *joint);
+            }
+        }
+        loaded.map_entities(&map);
+
+        for _ in 0..64 {
+            step(&mut original);
+            step(&mut loaded);
+        }
+        assert_eq!(original.get::<Position>(ball), loaded.get::<Position>(map[&ball]));
+        assert!(distance(&loaded, map[&hook], map[&ball]) < 61.0);
+
+        // a joint whose body wasn't loaded is dropped
+        map.remove(&hook);
+        let joint = loaded.query_filtered::<With<Joint>>().next().unwrap();
+        loaded.map_entities(&map);
+        assert!(!loaded.has::<Joint>(joint));
+    }
+
+    fn spawn_sleeper(world: &mut World, x: f32, xv: f32) -> Entity {
+        let e = world.spawn();
+        world.set(e, Position { x, y: 396.0 });
+        world.set(e, Velocity { xv, yv: 0.0 });
+        world.set(e, Shape { shape_type: ShapeType::Circle(4.0), colour: WHITE });
+        world.set(e, RigidBody::dynamic(1.0));
+        world.set(e, Sleep::default());
+        e
+    }
+
+    #[test]
+    fn resting_bodies_fall_asleep() {
+        let mut world = world();
+        let e = spawn_sleeper(&mut world, 200.0, 0.0);
+
+        for _ in 0..64 {
+            step(&mut world);
+        }
+        assert!(world.get::<Sleep>(e).unwrap().asleep);
+        assert_eq!(*world.get::<Velocity>(e).unwrap(), Velocity::default());
+        assert_eq!(world.sleeping.proxies().len(), 1);
+
+        // asleep it ignores gravity and stays exactly where it is
+        let position = *world.get::<Position>(e).unwrap();
+        for _ in 0..64 {
+            step(&mut world);
+        }
+        assert_eq!(*world.get::<Position>(e).unwrap(), position);
+
+        world.set(e, Force { fx: 1000.0, fy: 0.0 });
+        step(&mut world);
+        assert!(!world.get::<Sleep>(e).unwrap().asleep);
+        assert!(world.get::<Position>(e).unwrap().x > position.x);
+        assert!(world.sleeping.proxies().is_empty());
+    }
+
+    #[test]
+    fn sleepers_wake_when_hit() {
+        let mut world = world();
+        let sleeper = spawn_sleeper(&mut world, 200.0, 0.0);
+        for _ in 0..64 {
+            step(&mut world);
+        }
+        assert!(world.get::<Sleep>(sleeper).unwrap().asleep);
+
+        spawn_sleeper(&mut world, 150.0, 256.0);
+        for _ in 0..16 {
+            step(&mut world);
+        }
+        assert!(!world.get::<Sleep>(sleeper).unwrap().asleep);
+        assert!(world.get::<Position>(sleeper).unwrap().x > 200.0);
+    }
+
+    #[test]
+    fn only_dynamic_bodies_sleep() {
+        let mut world = world();
+        world.physics.gravity = Vector::zeros();
+        let e = world.spawn();
+        world.set(e, Position { x: 100.0, y: 100.0 });
+        world.set(e, Velocity { xv: 1.0, yv: 0.0 });
+        world.set(e, Shape { shape_type: ShapeType::Rectangle(20.0, 20.0), colour: WHITE });
+        world.set(e, RigidBody::kinematic());
+        world.set(e, Sleep::default());
+
+        for _ in 0..64 {
+            step(&mut world);
+        }
+        assert!(!world.get::<Sleep>(e).unwrap().asleep);
+        assert_eq!(*world.get::<Velocity>(e).unwrap(), Velocity { xv: 1.0, yv: 0.0 });
+    }
+
+    #[test]
+    fn bodies_fall_asleep_inside_the_screen() {
+        let mut world = world();
+        let e = spawn_sleeper(&mut world, 200.0, 0.0);
+        world.set(e, Position { x: 200.0, y: 399.0 });
+        world.set(e, Sleep { asleep: false, idle_time: 1.0 });
+        world.set(e, PreviousPosition { x: 200.0, y: 399.0 });
+
+        SleepSystem::process(&mut world);
+        assert!(world.get::<Sleep>(e).unwrap().asleep);
+        assert_eq!(*world.get::<Position>(e).unwrap(), Position { x: 200.0, y: 396.0 });
+    }
+
+    #[test]
+    fn long_sleepers_can_be_despawned() {
+        let mut world = world();
+        world.physics.despawn_after_sleeping = Some(1.0);
+        let e = spawn_sleeper(&mut world, 200.0, 0.0);
+
+        // half a second to fall asleep, then a second asleep
+        for _ in 0..95 {
+            step(&mut world);
+        }
+        assert!(world.is_live(e));
+        for _ in 0..2 {
+            step(&mut world);
+        }
+        assert!(!world.is_live(e));
+    }
+}
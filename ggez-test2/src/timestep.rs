@@ -0,0 +1,120 @@
+use std::time::Duration;
+
+/// Simulation clock stored on the `World`
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Time {
+    /// number of fixed steps run so far
+    pub tick: u64,
+    /// length of one fixed step in seconds
+    pub dt: f32,
+    /// how far between the last two steps the current frame is, 0..1, for interpolated drawing
+    pub alpha: f32,
+}
+
+/// Accumulates frame time and hands it out in fixed sized simulation steps
+#[derive(Debug, Clone)]
+pub struct FixedTimestep {
+    step: f64,
+    accumulator: f64,
+    max_steps: u32,
+    max_frame_time: f64,
+}
+
+impl FixedTimestep {
+    pub fn new(hz: f32) -> Self {
+        FixedTimestep {
+            step: 1.0 / hz as f64,
+            accumulator: 0.0,
+            max_steps: 5,
+            max_frame_time: 0.25,
+        }
+    }
+
+    /// Caps how many steps a single frame may run to catch up. Time beyond that is dropped,
+    /// so a slow simulation can't fall further behind each frame (the "spiral of death").
+    pub fn with_max_steps(mut self, max_steps: u32) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// Longest frame that is accounted for, e.g. after the window was dragged or the process stalled
+    pub fn with_max_frame_time(mut self, max_frame_time: Duration) -> Self {
+        self.max_frame_time = max_frame_time.as_secs_f64();
+        self
+    }
+
+    pub fn dt(&self) -> f32 {
+        self.step as f32
+    }
+
+    /// Adds a frame's worth of time and returns how many steps should be run
+    pub fn advance(&mut self, frame_time: Duration) -> u32 {
+        self.accumulator += frame_time.as_secs_f64().min(self.max_frame_time);
+
+        let mut steps = 0;
+        while self.accumulator >= self.step && steps < self.max_steps {
+            self.accumulator -= self.step;
+            steps += 1;
+        }
+
+        // still behind after running the maximum number of steps, drop the rest
+        if self.accumulator >= self.step {
+            self.accumulator %= self.step;
+        }
+
+        steps
+    }
+
+    /// Fraction of a step left in the accumulator, used to interpolate between the
+    /// previous and current simulation state when drawing
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator / self.step) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn steps_are_independent_of_frame_rate() {
+        // 64Hz and these frame lengths are exact in binary so no rounding creeps in
+        let mut slow = FixedTimestep::new(64.0);
+        let mut fast = FixedTimestep::new(64.0);
+
+        let slow_steps: u32 = (0..10).map(|_| slow.advance(Duration::from_micros(62_500))).sum();
+        let fast_steps: u32 = (0..40).map(|_| fast.advance(Duration::from_micros(15_625))).sum();
+
+        assert_eq!(slow_steps, 40);
+        assert_eq!(fast_steps, 40);
+    }
+
+    #[test]
+    fn alpha_is_leftover_fraction() {
+        let mut ts = FixedTimestep::new(100.0);
+
+        assert_eq!(ts.advance(ms(25)), 2);
+        assert!((ts.alpha() - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn catch_up_is_capped() {
+        let mut ts = FixedTimestep::new(100.0).with_max_steps(4);
+
+        assert_eq!(ts.advance(ms(100)), 4);
+        assert!(ts.alpha() < 1.0);
+        // the dropped time is not run on the next frame either
+        assert_eq!(ts.advance(ms(0)), 0);
+    }
+
+    #[test]
+    fn long_frames_are_clamped() {
+        let mut ts = FixedTimestep::new(10.0).with_max_steps(100).with_max_frame_time(ms(350));
+
+        assert_eq!(ts.advance(Duration::from_secs(10)), 3);
+    }
+}
@@ -0,0 +1,594 @@
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+use ggez::graphics::Rect;
+
+use crate::broadphase::{GridIndex, UniformGrid};
+use crate::camera::Camera2D;
+use crate::collision::{Manifold, TriggerEvent};
+use crate::commands::*;
+use crate::components::*;
+use crate::gendex::*;
+use crate::hooks::*;
+use crate::images::Images;
+use crate::physics::PhysicsSettings;
+use crate::reflect::Reflect;
+use crate::render::RenderLayers;
+use crate::rng::Random;
+use crate::systems::CONTACT_GRID_CELL_SIZE;
+use crate::timestep::Time;
+
+pub type Entity = GenerationalIndex;
+pub type EntityMap<T> = GenerationalIndexArray<T>;
+
+/// Implemented by every type that can be attached to an entity, giving generic code
+/// access to the `EntityMap` the component is stored in.
+pub trait Component: Reflect + Sized {
+    const NAME: &'static str;
+
+    fn storage(world: &World) -> &EntityMap<Self>;
+    fn storage_mut(world: &mut World) -> &mut EntityMap<Self>;
+}
+
+macro_rules! impl_components {
+    ($($ty:ident => $field:ident,)*) => {
+        $(
+            impl Component for $ty {
+                const NAME: &'static str = stringify!($ty);
+
+                fn storage(world: &World) -> &EntityMap<Self> {
+                    &world.$field
+                }
+
+                fn storage_mut(world: &mut World) -> &mut EntityMap<Self> {
+                    &mut world.$field
+                }
+            }
+        )*
+
+        impl World {
+            /// Names of every registered component type
+            pub fn component_types() -> &'static [&'static str] {
+                &[$(stringify!($ty)),*]
+            }
+
+            /// The component named `component` on `e` for generic inspection, see `Reflect`
+            pub fn reflect(&self, e: Entity, component: &str) -> Option<&dyn Reflect> {
+                match component {
+                    $(stringify!($ty) => self.$field.get(e).map(|c| c as &dyn Reflect),)*
+                    _ => None,
+                }
+            }
+
+            /// One entry per `component_types`, in the same order, with the component attached
+            /// to `e` if there is one. Cheaper than calling `reflect` for every type.
+            pub fn reflect_components(&self, e: Entity) -> impl Iterator<Item = Option<&dyn Reflect>> + '_ {
+                std::iter::empty()
+                    $(.chain(std::iter::once(self.$field.get(e).map(|c| c as &dyn Reflect))))*
+            }
+
+            /// Mutable version of `reflect`. Edits made through it do not fire any hooks.
+            pub fn reflect_mut(&mut self, e: Entity, component: &str) -> Option<&mut dyn Reflect> {
+                match component {
+                    $(stringify!($ty) => self.$field.get_mut(e).map(|c| c as &mut dyn Reflect),)*
+                    _ => None,
+                }
+            }
+
+            /// Names of all the component types currently attached to `e`
+            pub fn component_names(&self, e: Entity) -> Vec<&'static str> {
+                let mut names = Vec::new();
+                $(
+                    if self.$field.get(e).is_some() {
+                        names.push($ty::NAME);
+                    }
+                )*
+                names
+            }
+
+            /// Removes every component from `e`, firing the `on_remove` hooks for each
+            fn remove_all_components(&mut self, e: Entity) {
+                $(
+                    self.remove::<$ty>(e);
+                )*
+            }
+        }
+    };
+}
+
+impl_components! {
+    Position => position_components,
+    PreviousPosition => previous_position_components,
+    Velocity => velocity_components,
+    Force => force_components,
+    Shape => shape_components,
+    Rotation => rotation_components,
+    Anchor => anchor_components,
+    RenderLayer => render_layer_components,
+    ZIndex => z_index_components,
+    Sprite => sprite_components,
+    RigidBody => rigid_body_components,
+    Sleep => sleep_components,
+    Ccd => ccd_components,
+    CollisionFilter => collision_filter_components,
+    PhysicsMaterial => physics_material_components,
+    Joint => joint_components,
+    Name => name_components,
+    Particle => particle_tags,
+    Obstacle => obstacle_tags,
+}
+
+/// Decides whether an entity is yielded by `World::query_filtered`
+pub trait Filter {
+    fn matches(world: &World, e: Entity) -> bool;
+}
+
+/// Matches entities that have a `T` attached
+pub struct With<T>(PhantomData<T>);
+
+impl<T: Component> Filter for With<T> {
+    fn matches(world: &World, e: Entity) -> bool {
+        world.has::<T>(e)
+    }
+}
+
+/// Matches entities that do not have a `T` attached
+pub struct Without<T>(PhantomData<T>);
+
+impl<T: Component> Filter for Without<T> {
+    fn matches(world: &World, e: Entity) -> bool {
+        !world.has::<T>(e)
+    }
+}
+
+impl<A: Filter, B: Filter> Filter for (A, B) {
+    fn matches(world: &World, e: Entity) -> bool {
+        A::matches(world, e) && B::matches(world, e)
+    }
+}
+
+pub struct World {
+    pub entity_allocator: GenerationalIndexAllocator,
+    pub entities: Vec<Entity>,
+    pub position_components: EntityMap<Position>,
+    pub previous_position_components: EntityMap<PreviousPosition>,
+    pub velocity_components: EntityMap<Velocity>,
+    pub force_components: EntityMap<Force>,
+    pub shape_components: EntityMap<Shape>,
+    pub rotation_components: EntityMap<Rotation>,
+    pub anchor_components: EntityMap<Anchor>,
+    pub render_layer_components: EntityMap<RenderLayer>,
+    pub z_index_components: EntityMap<ZIndex>,
+    pub sprite_components: EntityMap<Sprite>,
+    pub rigid_body_components: EntityMap<RigidBody>,
+    pub sleep_components: EntityMap<Sleep>,
+    pub ccd_components: EntityMap<Ccd>,
+    pub collision_filter_components: EntityMap<CollisionFilter>,
+    pub physics_material_components: EntityMap<PhysicsMaterial>,
+    pub joint_components: EntityMap<Joint>,
+    pub name_components: EntityMap<Name>,
+    pub particle_tags: EntityMap<Particle>,
+    pub obstacle_tags: EntityMap<Obstacle>,
+    pub time: Time,
+    /// area entities are kept inside of, the window's screen coordinates or a virtual rect when headless
+    pub screen: Rect,
+    /// what part of the world is drawn into `screen`
+    pub camera: Camera2D,
+    /// which `RenderLayer`s are drawn
+    pub render_layers: RenderLayers,
+    /// images `Sprite`s are drawn with
+    pub images: Images,
+    pub rng: Random,
+    pub physics: PhysicsSettings,
+    /// changes deferred until the next `apply_commands`
+    pub commands: Commands,
+    /// overlapping shape pairs found by the last `ContactSystem` run
+    pub contacts: Vec<Manifold>,
+    /// (sensor, other) for every sensor overlap found by the last `ContactSystem` run, sorted
+    pub sensor_contacts: Vec<(Entity, Entity)>,
+    /// sensor overlaps starting, going on and ending, from the last `ContactSystem` run
+    pub trigger_events: Vec<TriggerEvent>,
+    /// kept between steps so `ContactSystem` reuses its buffers
+    pub broadphase: UniformGrid,
+    /// sleeping bodies, only rebuilt when one falls asleep or wakes up
+    pub sleeping: GridIndex,
+    /// all other shapes as of the end of the last step, see `SpatialIndexSystem`
+    pub spatial_index: GridIndex,
+    hooks: HashMap<TypeId, ComponentHooks>,
+    observers: HashMap<Entity, Vec<Observer>>,
+}
+
+impl World {
+    pub fn new() -> Self {
+        World {
+            entity_allocator: GenerationalIndexAllocator::new(),
+            entities: Vec::new(),
+            position_components: EntityMap::new(),
+            previous_position_components: EntityMap::new(),
+            velocity_components: EntityMap::new(),
+            force_components: EntityMap::new(),
+            shape_components: EntityMap::new(),
+            rotation_components: EntityMap::new(),
+            anchor_components: EntityMap::new(),
+            render_layer_components: EntityMap::new(),
+            z_index_components: EntityMap::new(),
+            sprite_components: EntityMap::new(),
+            rigid_body_components: EntityMap::new(),
+            sleep_components: EntityMap::new(),
+            ccd_components: EntityMap::new(),
+            collision_filter_components: EntityMap::new(),
+            physics_material_components: EntityMap::new(),
+            joint_components: EntityMap::new(),
+            name_components: EntityMap::new(),
+            particle_tags: EntityMap::new(),
+            obstacle_tags: EntityMap::new(),
+            time: Time::default(),
+            screen: Rect::default(),
+            camera: Camera2D::default(),
+            render_layers: RenderLayers::default(),
+            images: Images::default(),
+            rng: Random::new(0),
+            physics: PhysicsSettings::default(),
+            commands: Commands::new(),
+            contacts: Vec::new(),
+            sensor_contacts: Vec::new(),
+            trigger_events: Vec::new(),
+            broadphase: UniformGrid::new(CONTACT_GRID_CELL_SIZE),
+            sleeping: GridIndex::new(CONTACT_GRID_CELL_SIZE),
+            spatial_index: GridIndex::new(CONTACT_GRID_CELL_SIZE),
+            hooks: HashMap::new(),
+            observers: HashMap::new(),
+        }
+    }
+
+    pub fn spawn(&mut self) -> Entity {
+        let e = self.entity_allocator.allocate();
+        if e.index < self.entities.len() {
+            self.entities[e.index] = e;
+        } else {
+            self.entities.push(e);
+        }
+        e
+    }
+
+    pub fn is_live(&self, e: Entity) -> bool {
+        self.entity_allocator.is_live(e) && self.entities.get(e.index) == Some(&e)
+    }
+
+    /// Iterates over all live entities
+    pub fn live_entities(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.entities.iter().copied().filter(move |e| self.is_live(*e))
+    }
+
+    /// Iterates over all live entities that match the filter `F`, e.g. `With<Particle>`
+    pub fn query_filtered<F: Filter>(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.live_entities().filter(move |e| F::matches(self, *e))
+    }
+
+    pub fn get<T: Component>(&self, e: Entity) -> Option<&T> {
+        T::storage(self).get(e)
+    }
+
+    pub fn get_mut<T: Component>(&mut self, e: Entity) -> Option<&mut T> {
+        T::storage_mut(self).get_mut(e)
+    }
+
+    /// Attaches `value` to `e`, replacing any previous value. Fires `on_add` if the
+    /// entity did not have a `T` yet, then `on_insert`. Does nothing for dead entities.
+    pub fn set<T: Component>(&mut self, e: Entity, value: T) {
+        if !self.is_live(e) {
+            return;
+        }
+        let added = !self.has::<T>(e);
+        T::storage_mut(self).set(e, value);
+
+        if added {
+            self.fire::<T>(e, ComponentEventKind::Added);
+        }
+        self.fire::<T>(e, ComponentEventKind::Inserted);
+    }
+
+    /// Detaches a `T` from `e`. The `on_remove` hooks run before the value is taken
+    /// away so they can still read it.
+    pub fn remove<T: Component>(&mut self, e: Entity) -> Option<T> {
+        if !self.has::<T>(e) {
+            return None;
+        }
+        self.fire::<T>(e, ComponentEventKind::Removed);
+        T::storage_mut(self).remove(e)
+    }
+
+    /// Removes all components of `e` (firing their `on_remove` hooks), drops its observers
+    /// and frees the handle. Returns false if `e` was already dead.
+    pub fn despawn(&mut self, e: Entity) -> bool {
+        if !self.is_live(e) {
+            return false;
+        }
+        self.remove_all_components(e);
+        self.observers.remove(&e);
+        self.entity_allocator.deallocate(e)
+    }
+
+    /// Applies everything queued on `commands`, including commands queued by hooks
+    /// while applying
+    pub fn apply_commands(&mut self) {
+        while !self.commands.is_empty() {
+            for command in self.commands.take() {
+                command(self);
+            }
+        }
+    }
+
+    /// Called whenever a `T` is attached to an entity that did not have one
+    pub fn on_add<T: Component>(&mut self, hook: impl Fn(&mut World, Entity) + 'static) {
+        self.hooks.entry(TypeId::of::<T>()).or_default().on_add.push(Rc::new(hook));
+    }
+
+    /// Called every time a `T` is set on an entity, whether it is new or replaces an old value
+    pub fn on_insert<T: Component>(&mut self, hook: impl Fn(&mut World, Entity) + 'static) {
+        self.hooks.entry(TypeId::of::<T>()).or_default().on_insert.push(Rc::new(hook));
+    }
+
+    /// Called right before a `T` is removed from an entity, including on despawn
+    pub fn on_remove<T: Component>(&mut self, hook: impl Fn(&mut World, Entity) + 'static) {
+        self.hooks.entry(TypeId::of::<T>()).or_default().on_remove.push(Rc::new(hook));
+    }
+
+    /// Registers a callback for component changes on a single entity. Observers are
+    /// dropped when the entity is despawned.
+    pub fn observe<F: Fn(&mut World, &ComponentEvent) + 'static>(&mut self, e: Entity, observer: F) {
+        self.observers.entry(e).or_default().push(Rc::new(observer));
+    }
+
+    fn fire<T: Component>(&mut self, e: Entity, kind: ComponentEventKind) {
+        // hooks and observers are cloned out first so they are free to mutate the world
+        if let Some(hooks) = self.hooks.get(&TypeId::of::<T>()) {
+            let hooks = hooks.get(kind).to_vec();
+            for hook in hooks {
+                hook(self, e);
+            }
+        }
+
+        if let Some(observers) = self.observers.get(&e) {
+            let observers = observers.clone();
+            let event = ComponentEvent {
+                entity: e,
+                component: TypeId::of::<T>(),
+                component_name: T::NAME,
+                kind,
+            };
+            for observer in observers {
+                observer(self, &event);
+            }
+        }
+    }
+
+    pub fn has<T: Component>(&self, e: Entity) -> bool {
+        self.get::<T>(e).is_some()
+    }
+
+    /// Finds the first live entity carrying the given `Name`
+    pub fn find_by_name(&self, name: &str) -> Option<Entity> {
+        self.live_entities().find(|e| match self.name_components.get(*e) {
+            Some(n) => n.0 == name,
+            None => false,
+        })
+    }
+
+    /// Rewrites the entity handles components hold, for entities that were copied or loaded
+    /// into this world under new handles. `map` goes from the old handles to the new ones.
+    /// Joints with a body missing from it are removed, and the camera stops following a
+    /// target missing from it.
+    pub fn map_entities(&mut self, map: &HashMap<Entity, Entity>) {
+        for e in self.query_filtered::<With<Joint>>().collect::<Vec<_>>() {
+            if !self.get_mut::<Joint>(e).unwrap().map_entities(map) {
+                self.remove::<Joint>(e);
+            }
+        }
+        self.camera.target = self.camera.target.and_then(|e| map.get(&e).copied());
+    }
+
+    /// Returns a value whose `Debug` output shows the entity's name, handle and component types
+    pub fn debug_entity(&self, e: Entity) -> EntityDebug<'_> {
+        EntityDebug { world: self, entity: e }
+    }
+}
+
+impl Default for World {
+    fn default() -> Self {
+        World::new()
+    }
+}
+
+pub struct EntityDebug<'a> {
+    world: &'a World,
+    entity: Entity,
+}
+
+impl<'a> fmt::Debug for EntityDebug<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let e = self.entity;
+        if let Some(name) = self.world.name_components.get(e) {
+            write!(f, "{:?} ", name.0)?;
+        }
+        write!(f, "{}v{} ", e.index, e.generation)?;
+        if !self.world.is_live(e) {
+            write!(f, "(dead) ")?;
+        }
+        write!(f, "[{}]", self.world.component_names(e).join(", "))
+    }
+}
+
+impl fmt::Debug for World {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let entities: Vec<EntityDebug> = self.live_entities().map(|e| self.debug_entity(e)).collect();
+        f.debug_struct("World")
+            .field("live_entities", &self.entity_allocator.live_entity_count())
+            .field("allocated_entities", &self.entity_allocator.allocated_entity_count())
+            .field("entities", &entities)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_by_name() {
+        let mut world = World::new();
+        let a = world.spawn();
+        let b = world.spawn();
+        world.set(a, Name::new("a"));
+        world.set(b, Name::new("b"));
+
+        assert_eq!(world.find_by_name("b"), Some(b));
+        assert_eq!(world.find_by_name("c"), None);
+
+        world.despawn(b);
+        assert_eq!(world.find_by_name("b"), None);
+    }
+
+    #[test]
+    fn query_with_tag() {
+        let mut world = World::new();
+        let p1 = world.spawn();
+        let o = world.spawn();
+        let p2 = world.spawn();
+        world.set(p1, Particle);
+        world.set(o, Obstacle);
+        world.set(p2, Particle);
+        world.set(p2, Velocity::default());
+
+        assert_eq!(world.query_filtered::<With<Particle>>().collect::<Vec<_>>(), vec![p1, p2]);
+        assert_eq!(world.query_filtered::<With<Obstacle>>().collect::<Vec<_>>(), vec![o]);
+        assert_eq!(
+            world.query_filtered::<(With<Particle>, Without<Velocity>)>().collect::<Vec<_>>(),
+            vec![p1]
+        );
+    }
+
+    #[test]
+    fn debug_entity_lists_components() {
+        let mut world = World::new();
+        let e = world.spawn();
+        world.set(e, Name::new("wall"));
+        world.set(e, Position { x: 1.0, y: 2.0 });
+        world.set(e, Obstacle);
+
+        assert_eq!(format!("{:?}", world.debug_entity(e)), r#""wall" 0v0 [Position, Name, Obstacle]"#);
+    }
+
+    #[test]
+    fn hooks_fire_on_add_insert_and_remove() {
+        use std::cell::RefCell;
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut world = World::new();
+        let l = log.clone();
+        world.on_add::<Position>(move |_, e| l.borrow_mut().push(("add", e)));
+        let l = log.clone();
+        world.on_insert::<Position>(move |_, e| l.borrow_mut().push(("insert", e)));
+        let l = log.clone();
+        world.on_remove::<Position>(move |world, e| {
+            // the value is still readable while the hook runs
+            assert!(world.get::<Position>(e).is_some());
+            l.borrow_mut().push(("remove", e));
+        });
+
+        let e = world.spawn();
+        world.set(e, Position::default());
+        world.set(e, Position { x: 1.0, y: 1.0 });
+        world.set(e, Velocity::default());
+        assert_eq!(world.remove::<Position>(e), Some(Position { x: 1.0, y: 1.0 }));
+        assert_eq!(world.remove::<Position>(e), None);
+
+        assert_eq!(*log.borrow(), vec![("add", e), ("insert", e), ("insert", e), ("remove", e)]);
+    }
+
+    #[test]
+    fn despawn_fires_remove_hooks_and_observers() {
+        use std::cell::RefCell;
+
+        let removed = Rc::new(RefCell::new(Vec::new()));
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let mut world = World::new();
+        let r = removed.clone();
+        world.on_remove::<Shape>(move |_, e| r.borrow_mut().push(e));
+
+        let e = world.spawn();
+        let ev = events.clone();
+        world.observe(e, move |_, event| ev.borrow_mut().push((event.component_name, event.kind)));
+        world.set(e, Shape { shape_type: ShapeType::Circle(1.0), colour: ggez::graphics::WHITE });
+        world.set(e, Particle);
+
+        assert!(world.despawn(e));
+        assert!(!world.despawn(e));
+        assert_eq!(*removed.borrow(), vec![e]);
+        assert_eq!(*events.borrow(), vec![
+            ("Shape", ComponentEventKind::Added),
+            ("Shape", ComponentEventKind::Inserted),
+            ("Particle", ComponentEventKind::Added),
+            ("Particle", ComponentEventKind::Inserted),
+            ("Shape", ComponentEventKind::Removed),
+            ("Particle", ComponentEventKind::Removed),
+        ]);
+
+        // observers do not carry over to the next entity using the same slot
+        let e2 = world.spawn();
+        assert_eq!(e2.index, e.index);
+        world.set(e2, Particle);
+        assert_eq!(events.borrow().len(), 6);
+    }
+
+    #[test]
+    fn deferred_commands_fire_hooks_when_applied() {
+        use std::cell::Cell;
+
+        let added = Rc::new(Cell::new(0));
+        let removed = Rc::new(Cell::new(0));
+        let mut world = World::new();
+        let a = added.clone();
+        world.on_add::<Velocity>(move |world, e| {
+            a.set(a.get() + 1);
+            // hooks may queue up more work
+            world.commands.set(e, Particle);
+        });
+        let r = removed.clone();
+        world.on_remove::<Velocity>(move |_, _| r.set(r.get() + 1));
+
+        let e = world.spawn();
+        world.commands.set(e, Velocity::default());
+        assert_eq!(added.get(), 0);
+
+        world.apply_commands();
+        assert_eq!(added.get(), 1);
+        assert!(world.has::<Particle>(e));
+
+        world.commands.despawn(e);
+        world.commands.set(e, Position::default());
+        world.apply_commands();
+        assert_eq!(removed.get(), 1);
+        assert!(!world.is_live(e));
+        assert!(world.get::<Position>(e).is_none());
+    }
+
+    #[test]
+    fn reflect_components_by_name() {
+        let mut world = World::new();
+        let e = world.spawn();
+        world.set(e, Position { x: 3.0, y: 4.0 });
+        world.set(e, Shape { shape_type: ShapeType::Rectangle(20.0, 10.0), colour: ggez::graphics::WHITE });
+
+        assert!(World::component_types().contains(&"Shape"));
+        assert_eq!(world.reflect(e, "Position").unwrap().get_path::<f32>("x"), Ok(3.0));
+        assert!(world.reflect(e, "Velocity").is_none());
+        assert!(world.reflect(e, "Nonsense").is_none());
+
+        world.reflect_mut(e, "Shape").unwrap().set_path("shape_type.Rectangle.1", 5.0f32).unwrap();
+        assert_eq!(world.get::<Shape>(e).unwrap().shape_type, ShapeType::Rectangle(20.0, 5.0));
+    }
+}
@@ -0,0 +1,231 @@
+//! `#[derive(Reflect)]` for the component structs and enums in ggez-test2.
+//!
+//! The generated code refers to `crate::reflect`, so the derive can only be used
+//! from within ggez-test2 itself.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Ident, Index};
+
+#[proc_macro_derive(Reflect)]
+pub fn derive_reflect(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let body = match &input.data {
+        Data::Struct(data) => struct_body(&data.fields),
+        Data::Enum(data) => enum_body(name, data.variants.iter().collect()),
+        Data::Union(_) => {
+            return syn::Error::new_spanned(&input.ident, "Reflect cannot be derived for unions")
+                .to_compile_error()
+                .into();
+        }
+    };
+
+    let expanded = quote! {
+        impl #impl_generics crate::reflect::Reflect for #name #ty_generics #where_clause {
+            fn type_name(&self) -> &'static str {
+                stringify!(#name)
+            }
+
+            fn as_any(&self) -> &dyn std::any::Any {
+                self
+            }
+
+            fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+                self
+            }
+
+            #body
+        }
+    };
+
+    expanded.into()
+}
+
+/// Field name as seen by reflection paths and the expression that accesses it on `self`
+fn struct_fields(fields: &Fields) -> Vec<(String, TokenStream2, &syn::Type)> {
+    match fields {
+        Fields::Named(named) => named
+            .named
+            .iter()
+            .map(|f| {
+                let ident = f.ident.as_ref().unwrap();
+                (ident.to_string(), quote!(#ident), &f.ty)
+            })
+            .collect(),
+        Fields::Unnamed(unnamed) => unnamed
+            .unnamed
+            .iter()
+            .enumerate()
+            .map(|(i, f)| {
+                let index = Index::from(i);
+                (i.to_string(), quote!(#index), &f.ty)
+            })
+            .collect(),
+        Fields::Unit => Vec::new(),
+    }
+}
+
+fn struct_body(fields: &Fields) -> TokenStream2 {
+    let fields = struct_fields(fields);
+    let names: Vec<&String> = fields.iter().map(|(n, _, _)| n).collect();
+    let accessors: Vec<&TokenStream2> = fields.iter().map(|(_, a, _)| a).collect();
+    let types: Vec<&syn::Type> = fields.iter().map(|(_, _, t)| *t).collect();
+
+    quote! {
+        fn fields(&self) -> Vec<crate::reflect::FieldInfo> {
+            vec![
+                #(crate::reflect::FieldInfo { name: #names, type_name: stringify!(#types) },)*
+            ]
+        }
+
+        #[allow(unused_variables)]
+        fn visit_fields(&self, visit: &mut dyn FnMut(&'static str, &dyn crate::reflect::Reflect)) {
+            #(visit(#names, &self.#accessors);)*
+        }
+
+        fn field(&self, name: &str) -> Option<&dyn crate::reflect::Reflect> {
+            match name {
+                #(#names => Some(&self.#accessors),)*
+                _ => None,
+            }
+        }
+
+        fn field_mut(&mut self, name: &str) -> Option<&mut dyn crate::reflect::Reflect> {
+            match name {
+                #(#names => Some(&mut self.#accessors),)*
+                _ => None,
+            }
+        }
+    }
+}
+
+fn enum_body(name: &Ident, variants: Vec<&syn::Variant>) -> TokenStream2 {
+    let mut variant_arms = Vec::new();
+    let mut fields_arms = Vec::new();
+    let mut field_arms = Vec::new();
+
+    for variant in variants {
+        let ident = &variant.ident;
+        let variant_name = ident.to_string();
+
+        // bindings used when destructuring the variant, plus the field name each one maps to
+        let (pattern, bindings): (TokenStream2, Vec<(String, Ident, &syn::Type)>) = match &variant.fields {
+            Fields::Named(named) => {
+                let bindings: Vec<_> = named
+                    .named
+                    .iter()
+                    .map(|f| {
+                        let field = f.ident.clone().unwrap();
+                        (field.to_string(), field, &f.ty)
+                    })
+                    .collect();
+                let idents = bindings.iter().map(|(_, b, _)| b);
+                (quote!(#name::#ident { #(#idents),* }), bindings)
+            }
+            Fields::Unnamed(unnamed) => {
+                let bindings: Vec<_> = unnamed
+                    .unnamed
+                    .iter()
+                    .enumerate()
+                    .map(|(i, f)| (i.to_string(), format_ident!("f{}", i), &f.ty))
+                    .collect();
+                let idents = bindings.iter().map(|(_, b, _)| b);
+                (quote!(#name::#ident(#(#idents),*)), bindings)
+            }
+            Fields::Unit => (quote!(#name::#ident), Vec::new()),
+        };
+
+        let names: Vec<String> = bindings.iter().map(|(n, _, _)| n.clone()).collect();
+        let idents: Vec<Ident> = bindings.iter().map(|(_, b, _)| b.clone()).collect();
+        let types: Vec<&syn::Type> = bindings.iter().map(|(_, _, t)| *t).collect();
+
+        let wildcard = match &variant.fields {
+            Fields::Named(_) => quote!(#name::#ident { .. }),
+            Fields::Unnamed(_) => quote!(#name::#ident(..)),
+            Fields::Unit => quote!(#name::#ident),
+        };
+        variant_arms.push(quote! {
+            #wildcard => #variant_name,
+        });
+        fields_arms.push(quote! {
+            #wildcard => vec![
+                #(crate::reflect::FieldInfo { name: #names, type_name: stringify!(#types) },)*
+            ],
+        });
+        field_arms.push((pattern, names, idents));
+    }
+
+    let field_ref_arms = field_arms.iter().map(|(pattern, names, idents)| {
+        quote! {
+            #[allow(unused_variables)]
+            #pattern => match name {
+                #(#names => Some(#idents as &dyn crate::reflect::Reflect),)*
+                _ => None,
+            },
+        }
+    });
+    let visit_arms = field_arms.iter().map(|(pattern, names, idents)| {
+        quote! {
+            #[allow(unused_variables)]
+            #pattern => {
+                #(visit(#names, #idents);)*
+            },
+        }
+    });
+    let field_mut_arms = field_arms.iter().map(|(pattern, names, idents)| {
+        quote! {
+            #[allow(unused_variables)]
+            #pattern => match name {
+                #(#names => Some(#idents as &mut dyn crate::reflect::Reflect),)*
+                _ => None,
+            },
+        }
+    });
+
+    quote! {
+        fn variant(&self) -> Option<&'static str> {
+            Some(match self {
+                #(#variant_arms)*
+            })
+        }
+
+        fn fields(&self) -> Vec<crate::reflect::FieldInfo> {
+            match self {
+                #(#fields_arms)*
+            }
+        }
+
+        #[allow(unused_variables)]
+        fn visit_fields(&self, visit: &mut dyn FnMut(&'static str, &dyn crate::reflect::Reflect)) {
+            match self {
+                #(#visit_arms)*
+            }
+        }
+
+        // the active variant's name resolves to the enum itself, so paths read like
+        // `shape_type.Circle.0` and fail if a different variant is active
+        fn field(&self, name: &str) -> Option<&dyn crate::reflect::Reflect> {
+            if self.variant() == Some(name) {
+                return Some(self);
+            }
+            match self {
+                #(#field_ref_arms)*
+            }
+        }
+
+        fn field_mut(&mut self, name: &str) -> Option<&mut dyn crate::reflect::Reflect> {
+            if self.variant() == Some(name) {
+                return Some(self);
+            }
+            match self {
+                #(#field_mut_arms)*
+            }
+        }
+    }
+}